@@ -0,0 +1,386 @@
+use crate::{
+    config::{
+        read_config::{
+            bridge_protocol_from_str, duplicate_processor_name, log_compression_from_str,
+            BYTES_PER_MB,
+        },
+        BridgeInfo, BridgeProtocol, BuadRate, Config, LogCompression, ProcessorInfo,
+        DEFAULT_BAUD_RATE,
+    },
+    utils::{
+        transport::{is_unlisted_port, RFC2217_SCHEME},
+        user_io::{BoxErr, BoxResult, RaisedError},
+    },
+};
+use serialport::SerialPortType;
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Slowest `--replay-speed` other than 0; slower ones would sleep for days
+/// on a gap.
+pub const MIN_REPLAY_SPEED: f64 = 0.001;
+
+pub const USAGE: &str = "\
+usage: jfconsole [options]
+
+With no options the config is selected or created interactively. When
+stdin is not a terminal its lines go to the first processor, and the session
+runs on after it closes until SIGINT or a `quit` on the control socket.
+
+options:
+  --config <path.json>          use this config file
+  --port <path[:baud[:name]]>   use this port (repeatable, instead of --config);
+                                loop:// echoes writes back, pty:// opens a
+                                new pseudo-terminal for a simulator to attach,
+                                rfc2217://host:port is a terminal server port
+  --project <name>              project name (overrides the config's)
+  --log-dir <dir>               directory for logs and cmd history
+  --log-max-size <MB>           start a new log segment after this many MB
+  --log-interval <minutes>      start a new log segment this often (may be
+                                fractional, eg 0.5)
+  --log-keep <count>            delete all but the newest log segments
+  --log-compress <mode>         none, gzip (write .log.gz), zstd (write
+                                .log.zst) or rotated (gzip closed segments
+                                in the background)
+  --capture                     also record raw rx/tx bytes to a .cap file
+  --bridge <tcp port>           serve each processor over TCP, the first on
+                                this port and the rest on the ports after it
+                                (processors with a configured bridge keep it)
+  --bridge-protocol <protocol>  raw (default) or telnet, for --bridge
+  --bridge-bind <address>       address bridges listen on (default
+                                127.0.0.1, 0.0.0.0 serves the network)
+  --control                     accept commands from scripts on
+                                <log dir>/jfconsole.sock
+  --http <tcp port>             serve a browser dashboard, a websocket of
+                                log lines and POST /send on 127.0.0.1
+  --tui                         split-pane terminal UI, one pane per processor
+  --replay <path>               play a .cap or .log file (or .gz or .zst of
+                                either) back instead of using ports;
+                                --config supplies triggers and styles
+  --replay-speed <factor>       replay speed, 0 for no delays, else at least
+                                0.001 (default 1)
+  --step                        replay one chunk or line per Enter, q to stop
+  -h, --help                    print this message";
+
+pub struct PortArg {
+    pub port_name: String,
+    pub baud_rate: BuadRate,
+    pub processor_name: String,
+}
+
+#[derive(Default)]
+pub struct CliArgs {
+    pub config_path: Option<PathBuf>,
+    pub ports: Vec<PortArg>,
+    pub project_name: Option<String>,
+    pub log_dir: Option<PathBuf>,
+    pub log_max_size_mb: Option<u64>,
+    pub log_interval_min: Option<f64>,
+    pub log_keep: Option<usize>,
+    pub log_compression: Option<LogCompression>,
+    pub capture: bool,
+    pub bridge_port: Option<u16>,
+    pub bridge_protocol: BridgeProtocol,
+    pub bridge_bind: Option<IpAddr>,
+    pub control: bool,
+    pub http_port: Option<u16>,
+    pub tui: bool,
+    pub replay_path: Option<PathBuf>,
+    pub replay_speed: Option<f64>,
+    pub replay_step: bool,
+    pub help: bool,
+}
+
+impl PortArg {
+    /// Parses `path`, `path:baud` or `path:baud:name`. The path itself may
+    /// contain `:`, so fields are split off from the right, but never into
+    /// the `host:port` of an `rfc2217://` path.
+    pub fn parse(arg: &str) -> BoxResult<Self> {
+        let fields: Vec<_> = arg.rsplitn(3, ':').collect();
+        let parse_baud = |baud: &str| baud.parse::<BuadRate>().ok();
+        let min_path_len = match arg.strip_prefix(RFC2217_SCHEME) {
+            Some(addr) => {
+                let host_port: Vec<_> = addr.splitn(3, ':').take(2).collect();
+                RFC2217_SCHEME.len() + host_port.join(":").len()
+            }
+            None => 0,
+        };
+        let (port_name, baud_rate, processor_name) = match fields[..] {
+            [name, baud, path] if parse_baud(baud).is_some() && path.len() >= min_path_len => {
+                (path, parse_baud(baud).unwrap(), Some(name))
+            }
+            [baud, _, ..]
+                if parse_baud(baud).is_some() && arg.len() - baud.len() > min_path_len =>
+            {
+                let path = &arg[..arg.len() - baud.len() - 1];
+                (path, parse_baud(baud).unwrap(), None)
+            }
+            _ => (arg, DEFAULT_BAUD_RATE, None),
+        };
+        if port_name.is_empty() {
+            return Err(RaisedError::new("empty port path"));
+        }
+        let default_name = || {
+            let file_name = Path::new(port_name).file_name();
+            file_name.map_or(port_name.into(), |f| f.to_string_lossy().into())
+        };
+        Ok(Self {
+            port_name: port_name.to_string(),
+            baud_rate,
+            processor_name: processor_name.map_or_else(default_name, String::from),
+        })
+    }
+}
+
+impl CliArgs {
+    pub fn from_env() -> BoxResult<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> BoxResult<Self> {
+        let mut cli_args = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                let msg = format!("missing value for {}", arg);
+                args.next().ok_or_else(|| RaisedError::new(&msg))
+            };
+            let count = |value: String| match value.parse() {
+                Ok(count) if count > 0 => Ok(count),
+                _ => Err(RaisedError::new(&format!("{} needs a count above 0", arg))),
+            };
+            match arg.as_str() {
+                "--config" => cli_args.config_path = Some(value()?.into()),
+                "--port" => cli_args.ports.push(PortArg::parse(&value()?)?),
+                "--project" => cli_args.project_name = Some(value()?),
+                "--log-dir" => cli_args.log_dir = Some(value()?.into()),
+                "--log-max-size" => {
+                    let size_mb = count(value()?)? as u64;
+                    if size_mb.checked_mul(BYTES_PER_MB).is_none() {
+                        return Err(RaisedError::new("--log-max-size is too large"));
+                    }
+                    cli_args.log_max_size_mb = Some(size_mb);
+                }
+                "--log-interval" => {
+                    let minutes = value()?.parse().ok().filter(|min: &f64| {
+                        *min > 0.0 && Duration::try_from_secs_f64(*min * 60.0).is_ok()
+                    });
+                    let minutes = minutes.ok_or_else(|| RaisedError::new("bad --log-interval"))?;
+                    cli_args.log_interval_min = Some(minutes);
+                }
+                "--log-keep" => cli_args.log_keep = Some(count(value()?)?),
+                "--log-compress" => {
+                    cli_args.log_compression = Some(log_compression_from_str(&value()?)?)
+                }
+                "--capture" => cli_args.capture = true,
+                "--control" => cli_args.control = true,
+                "--http" => {
+                    let port = value()?.parse().ok();
+                    let port = port.ok_or_else(|| RaisedError::new("bad --http port"))?;
+                    cli_args.http_port = Some(port);
+                }
+                "--bridge" => {
+                    let port = value()?.parse().ok().filter(|port| *port > 0);
+                    let port = port.ok_or_else(|| RaisedError::new("bad --bridge port"))?;
+                    cli_args.bridge_port = Some(port);
+                }
+                "--bridge-protocol" => {
+                    cli_args.bridge_protocol = bridge_protocol_from_str(&value()?)?
+                }
+                "--bridge-bind" => {
+                    let addr = value()?.parse().ok();
+                    let addr = addr.ok_or_else(|| RaisedError::new("bad --bridge-bind address"))?;
+                    cli_args.bridge_bind = Some(addr);
+                }
+                "--tui" => cli_args.tui = true,
+                "--replay" => cli_args.replay_path = Some(value()?.into()),
+                "--replay-speed" => {
+                    let speed = value()?.parse::<f64>().ok().filter(|speed| {
+                        *speed == 0.0 || (MIN_REPLAY_SPEED..=f64::MAX).contains(speed)
+                    });
+                    let speed = speed.ok_or_else(|| RaisedError::new("bad --replay-speed"))?;
+                    cli_args.replay_speed = Some(speed);
+                }
+                "--step" => cli_args.replay_step = true,
+                "-h" | "--help" => cli_args.help = true,
+                _ => return Err(RaisedError::new(&format!("unknown argument {:?}", arg))),
+            }
+        }
+        if cli_args.config_path.is_some() && !cli_args.ports.is_empty() {
+            return Err(RaisedError::new("--config and --port are exclusive"));
+        }
+        if cli_args.replay_path.is_some() && !cli_args.ports.is_empty() {
+            return Err(RaisedError::new("--replay and --port are exclusive"));
+        }
+        Ok(cli_args)
+    }
+
+    /// True when the args fully describe the session, so no prompts are needed.
+    pub fn is_scripted(&self) -> bool {
+        self.config_path.is_some() || !self.ports.is_empty()
+    }
+
+    /// Builds the config named by `--config` or the `--port` list, applying
+    /// `--project` and `--log-dir` on top.
+    pub fn scripted_config(&self, procs: Vec<ProcessorInfo>) -> BoxResult<Config> {
+        let mut cfg = match &self.config_path {
+            Some(config_path) => Config::read_config_path(config_path.clone(), &procs)?,
+            None => self.ports_config(procs)?,
+        };
+        self.apply_overrides(&mut cfg)?;
+        Ok(cfg)
+    }
+
+    pub fn apply_overrides(&self, cfg: &mut Config) -> BoxResult<()> {
+        if let Some(project_name) = &self.project_name {
+            cfg.project_name = project_name.clone();
+            if self.log_dir.is_none() {
+                cfg.log_dir = PathBuf::from(project_name);
+            }
+        }
+        if let Some(log_dir) = &self.log_dir {
+            cfg.log_dir = log_dir.clone();
+        }
+        let rotation = &mut cfg.log_rotation;
+        if let Some(max_size_mb) = self.log_max_size_mb {
+            let max_size = max_size_mb.checked_mul(BYTES_PER_MB);
+            let msg = "--log-max-size is too large";
+            rotation.max_size = Some(max_size.ok_or_else(|| RaisedError::new(msg))?);
+        }
+        if let Some(interval_min) = self.log_interval_min {
+            let interval = Duration::try_from_secs_f64(interval_min * 60.0).box_err()?;
+            rotation.interval = Some(interval);
+        }
+        if let Some(keep) = self.log_keep {
+            rotation.keep = Some(keep);
+        }
+        if let Some(log_compression) = self.log_compression {
+            cfg.log_compression = log_compression;
+        }
+        if let Some(first_port) = self.bridge_port {
+            for (idx, processor) in cfg.processors.iter_mut().enumerate() {
+                let tcp_port = u16::try_from(idx)
+                    .ok()
+                    .and_then(|idx| first_port.checked_add(idx));
+                let Some(tcp_port) = tcp_port else {
+                    let msg = format!(
+                        "--bridge {} runs out of tcp ports at processor {:?}",
+                        first_port, processor.processor_name
+                    );
+                    return Err(RaisedError::new(&msg));
+                };
+                processor.bridge.get_or_insert(BridgeInfo {
+                    tcp_port,
+                    protocol: self.bridge_protocol,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn ports_config(&self, mut procs: Vec<ProcessorInfo>) -> BoxResult<Config> {
+        let mut processors = vec![];
+        for port in self.ports.iter() {
+            let mut processor = match procs.iter().position(|p| p.port_name == port.port_name) {
+                Some(idx) => procs.remove(idx),
+                None if is_unlisted_port(&port.port_name) => {
+                    ProcessorInfo::new(port.port_name.clone(), SerialPortType::Unknown)
+                }
+                None => {
+                    let msg = format!("port {:?} not found", port.port_name);
+                    return Err(RaisedError::new(&msg));
+                }
+            };
+            processor.baud_rate = port.baud_rate;
+            processor.processor_name = port.processor_name.clone();
+            processors.push(processor);
+        }
+        if let Some(name) = duplicate_processor_name(&processors) {
+            let msg = format!("processor name {:?} used for more than one port", name);
+            return Err(RaisedError::new(&msg));
+        }
+
+        let project_name = self
+            .project_name
+            .clone()
+            .unwrap_or_else(|| "jfconsole".into());
+        Ok(Config {
+            processors: processors.into(),
+            project_path: PathBuf::from(format!("./config/{}.json", project_name)),
+            log_dir: PathBuf::from(&project_name),
+            log_rotation: Default::default(),
+            log_compression: Default::default(),
+            highlights: Box::new([]),
+            project_name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::user_io::box_err_msg;
+
+    fn parse(args: &[&str]) -> BoxResult<CliArgs> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rejects_log_limits_that_do_not_fit() {
+        assert!(parse(&["--log-max-size", "99999999999999999"]).is_err());
+        for minutes in ["inf", "NaN", "1e300", "0", "-1"] {
+            assert!(parse(&["--log-interval", minutes]).is_err(), "{}", minutes);
+        }
+        let cli_args = parse(&["--log-max-size", "2", "--log-interval", "0.5"]).unwrap();
+        assert_eq!(cli_args.log_max_size_mb, Some(2));
+        assert_eq!(cli_args.log_interval_min, Some(0.5));
+    }
+
+    #[test]
+    fn gives_each_processor_its_own_bridge_port() {
+        let config = || Config {
+            processors: ["a", "b"]
+                .iter()
+                .map(|name| {
+                    let mut p = ProcessorInfo::new("loop://".into(), SerialPortType::Unknown);
+                    p.processor_name = name.to_string();
+                    p
+                })
+                .collect(),
+            project_path: PathBuf::new(),
+            log_dir: PathBuf::new(),
+            log_rotation: Default::default(),
+            log_compression: Default::default(),
+            highlights: Box::new([]),
+            project_name: "test".into(),
+        };
+        let ports = |cfg: &Config| -> Vec<_> {
+            cfg.processors
+                .iter()
+                .map(|p| p.bridge.map(|bridge| bridge.tcp_port))
+                .collect()
+        };
+
+        let cli_args = parse(&["--bridge", "65535"]).unwrap();
+        let e = cli_args.apply_overrides(&mut config()).unwrap_err();
+        assert!(box_err_msg(&e).contains("\"b\""), "{}", box_err_msg(&e));
+
+        let cli_args = parse(&["--bridge", "65534"]).unwrap();
+        let mut cfg = config();
+        cli_args.apply_overrides(&mut cfg).unwrap();
+        assert_eq!(ports(&cfg), [Some(65534), Some(65535)]);
+    }
+
+    #[test]
+    fn rejects_replay_speeds_too_slow_to_sleep() {
+        for speed in ["1e-30", "-1", "inf", "NaN"] {
+            assert!(parse(&["--replay-speed", speed]).is_err(), "{}", speed);
+        }
+        for speed in ["0", "0.001", "1e300"] {
+            assert!(parse(&["--replay-speed", speed]).is_ok(), "{}", speed);
+        }
+    }
+}
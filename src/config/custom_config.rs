@@ -1,19 +1,29 @@
 use crate::{
-    config::{Config, ProcessorInfo},
-    utils::user_io::{read_and_parse_user_entry, BoxErr, BoxResult, ReadAndParseUserEntryRes},
+    config::{
+        read_config::{
+            data_bits_from_u8, data_bits_to_u8, flow_control_to_str, parity_from_str,
+            parity_to_str, port_type_to_str, stop_bits_from_u8, stop_bits_to_u8,
+        },
+        Config, ProcessorInfo,
+    },
+    utils::{
+        transport::is_unlisted_port,
+        user_io::{
+            read_and_parse_user_entry, BoxErr, BoxResult, RaisedError, ReadAndParseUserEntryRes,
+        },
+    },
 };
 use rustyline::{error::ReadlineError, Editor};
-use std::{num::ParseIntError, path::PathBuf};
+use serialport::{DataBits, FlowControl, Parity, SerialPortType, StopBits};
+use std::path::PathBuf;
 
 pub enum UserSelectConfigRes {
     Proc(ProcessorInfo),
     NoneRemaining,
     EntryOutOfRange,
+    /// Neither an index nor a path that can be opened.
+    UnknownPort(String),
     EmptyEntry,
-    ParseErr {
-        e: ParseIntError,
-        user_entry: String,
-    },
     IOErr(std::io::Error),
     ReadErr(ReadlineError),
 }
@@ -52,10 +62,99 @@ impl ProcessorInfo {
                 ReadAndParseUserEntryRes::Ok(baud_rate) => break baud_rate,
             }
         };
-        Ok(println!(
+        println!(
             "> Set baudrate of {:?} as {}\n",
             self.processor_name, self.baud_rate
-        ))
+        );
+
+        (self.data_bits, self.parity, self.stop_bits) = loop {
+            println!("Line settings options for {:?}:", self.processor_name);
+            println!("1) 8N1");
+            println!("2) 7E1");
+            println!("3) 8N2");
+            println!("_) custom value (eg 7O2)");
+
+            match read_and_parse_user_entry::<String>("Enter 1, 2, 3, or a custom value") {
+                ReadAndParseUserEntryRes::Ok(entry) => {
+                    let entry = match entry.as_str() {
+                        "1" => "8N1",
+                        "2" => "7E1",
+                        "3" => "8N2",
+                        entry => entry,
+                    };
+                    match parse_line_settings(entry) {
+                        Ok(settings) => break settings,
+                        Err(_) => println!("> Invalid Entry {:?}\n", entry),
+                    }
+                }
+                ReadAndParseUserEntryRes::ParseErr { e, user_entry } => {
+                    println!("> Invalid Entry {:?} {:?}\n", user_entry, e);
+                }
+                ReadAndParseUserEntryRes::IOErr(e) => return Err(Box::new(e)),
+                ReadAndParseUserEntryRes::ReadErr(e) => return Err(Box::new(e)),
+                ReadAndParseUserEntryRes::EmptyEntry => println!("> Empty Entry, try again\n"),
+            }
+        };
+        println!(
+            "> Set line settings of {:?} as {}{}{}\n",
+            self.processor_name,
+            data_bits_to_u8(self.data_bits),
+            parity_to_str(self.parity)[..1].to_uppercase(),
+            stop_bits_to_u8(self.stop_bits),
+        );
+
+        self.flow_control = loop {
+            println!("Flow control options for {:?}:", self.processor_name);
+            println!("1) none");
+            println!("2) hardware (RTS/CTS)");
+            println!("3) software (XON/XOFF)");
+
+            match read_and_parse_user_entry("Enter 1, 2, or 3") {
+                ReadAndParseUserEntryRes::ParseErr { e, user_entry } => {
+                    println!("> Invalid Entry {:?} {:?}\n", user_entry, e);
+                }
+                ReadAndParseUserEntryRes::IOErr(e) => return Err(Box::new(e)),
+                ReadAndParseUserEntryRes::ReadErr(e) => return Err(Box::new(e)),
+                ReadAndParseUserEntryRes::EmptyEntry => println!("> Empty Entry, try again\n"),
+                ReadAndParseUserEntryRes::Ok(1) => break FlowControl::None,
+                ReadAndParseUserEntryRes::Ok(2) => break FlowControl::Hardware,
+                ReadAndParseUserEntryRes::Ok(3) => break FlowControl::Software,
+                ReadAndParseUserEntryRes::Ok(_) => println!("> Invalid Entry, try again\n"),
+            }
+        };
+        println!(
+            "> Set flow control of {:?} as {}\n",
+            self.processor_name,
+            flow_control_to_str(self.flow_control)
+        );
+        Ok(())
+    }
+}
+
+/// Parses the usual `<data bits><parity><stop bits>` shorthand, eg `7E1`.
+pub fn parse_line_settings(entry: &str) -> BoxResult<(DataBits, Parity, StopBits)> {
+    let entry = entry.to_ascii_lowercase();
+    let mut chars = entry.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some(data_bits), Some(parity), Some(stop_bits), None) => Ok((
+            data_bits_from_u8(data_bits.to_digit(10).unwrap_or(0) as u8)?,
+            parity_from_str(&parity.to_string())?,
+            stop_bits_from_u8(stop_bits.to_digit(10).unwrap_or(0) as u8)?,
+        )),
+        _ => Err(RaisedError::new("expected line settings like 8N1")),
+    }
+}
+
+/// What kind of port `port_type` is, eg `usb 0403:6001 FT232R USB UART`.
+pub fn port_type_label(port_type: &SerialPortType) -> String {
+    match port_type {
+        SerialPortType::UsbPort(usb) => format!(
+            "usb {:04x}:{:04x} {}",
+            usb.vid,
+            usb.pid,
+            usb.product.as_deref().unwrap_or_default()
+        ),
+        port_type => port_type_to_str(port_type).to_string(),
     }
 }
 
@@ -63,22 +162,31 @@ impl ProcessorInfo {
     pub fn user_select(procs: &mut Vec<ProcessorInfo>) -> UserSelectConfigRes {
         println!("Serial Ports to select from:");
         for (idx, p) in procs.iter().enumerate() {
-            println!("{}) {} {}", idx + 1, p.port_name, p.processor_name,);
+            println!(
+                "{}) {} {}",
+                idx + 1,
+                p.port_name,
+                port_type_label(&p.port_type).trim_end()
+            );
         }
 
-        let msg = "Enter index of port to add (or enter to stop)";
-        match read_and_parse_user_entry::<usize>(msg) {
-            ReadAndParseUserEntryRes::Ok(entered_idx) => {
-                if 1 > entered_idx || entered_idx > procs.len() {
+        let msg = "Enter index of port to add, a device path (or enter to stop)";
+        match read_and_parse_user_entry::<String>(msg) {
+            ReadAndParseUserEntryRes::Ok(entry) => match entry.parse::<usize>() {
+                Ok(entered_idx) if 1 > entered_idx || entered_idx > procs.len() => {
                     UserSelectConfigRes::EntryOutOfRange
-                } else {
-                    UserSelectConfigRes::Proc(procs.remove(entered_idx - 1))
                 }
-            }
+                Ok(entered_idx) => UserSelectConfigRes::Proc(procs.remove(entered_idx - 1)),
+                Err(_) => match procs.iter().position(|p| p.port_name == entry) {
+                    Some(idx) => UserSelectConfigRes::Proc(procs.remove(idx)),
+                    None if is_unlisted_port(&entry) => UserSelectConfigRes::Proc(
+                        ProcessorInfo::new(entry, SerialPortType::Unknown),
+                    ),
+                    None => UserSelectConfigRes::UnknownPort(entry),
+                },
+            },
             ReadAndParseUserEntryRes::IOErr(e) => UserSelectConfigRes::IOErr(e),
-            ReadAndParseUserEntryRes::ParseErr { e, user_entry } => {
-                UserSelectConfigRes::ParseErr { e, user_entry }
-            }
+            ReadAndParseUserEntryRes::ParseErr { e, .. } => match e {},
             ReadAndParseUserEntryRes::ReadErr(e) => UserSelectConfigRes::ReadErr(e),
             ReadAndParseUserEntryRes::EmptyEntry => UserSelectConfigRes::EmptyEntry,
         }
@@ -95,6 +203,12 @@ impl Config {
                     if let Err(e) = p.user_config() {
                         println!("> error {:?}", e);
                         procs.push(p);
+                    } else if selected
+                        .iter()
+                        .any(|s: &ProcessorInfo| s.processor_name == p.processor_name)
+                    {
+                        println!("> Nickname {:?} is taken\n", p.processor_name);
+                        procs.push(p);
                     } else {
                         selected.push(p);
                         continue;
@@ -103,8 +217,8 @@ impl Config {
                 UserSelectConfigRes::EntryOutOfRange => {
                     println!("> Entry Out of Range\n");
                 }
-                UserSelectConfigRes::ParseErr { e, user_entry } => {
-                    println!("> Invalid Entry {:?} {:?}\n", user_entry, e);
+                UserSelectConfigRes::UnknownPort(entry) => {
+                    println!("> No port at {:?}\n", entry);
                 }
                 UserSelectConfigRes::EmptyEntry => break,
                 UserSelectConfigRes::NoneRemaining => {
@@ -124,9 +238,36 @@ impl Config {
         let project_path = PathBuf::from(format!("./config/{}.json", project_name));
         Self {
             processors: selected.into(),
+            log_dir: PathBuf::from(&project_name),
+            log_rotation: Default::default(),
+            log_compression: Default::default(),
+            highlights: Box::new([]),
             project_name,
             project_path,
         }
         .save_config_file()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_line_settings_shorthand() {
+        let eight_n_one = (DataBits::Eight, Parity::None, StopBits::One);
+        assert_eq!(parse_line_settings("8N1").unwrap(), eight_n_one);
+        assert_eq!(parse_line_settings("8n1").unwrap(), eight_n_one);
+        assert_eq!(
+            parse_line_settings("7E2").unwrap(),
+            (DataBits::Seven, Parity::Even, StopBits::Two)
+        );
+        assert_eq!(
+            parse_line_settings("5O1").unwrap(),
+            (DataBits::Five, Parity::Odd, StopBits::One)
+        );
+        for entry in ["9X3", "9N1", "8X1", "8N3", "8N", "8N1 ", "", "N81"] {
+            assert!(parse_line_settings(entry).is_err(), "{:?}", entry);
+        }
+    }
+}
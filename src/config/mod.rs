@@ -1,26 +1,218 @@
+pub mod cli_args;
 pub mod custom_config;
 pub mod read_config;
 
 pub type BuadRate = u32;
 pub const DEFAULT_BAUD_RATE: BuadRate = 115_200;
+pub const DEFAULT_TX_CHAR_DELAY: Duration = Duration::from_millis(1);
+pub const DEFAULT_TX_LINE_DELAY: Duration = Duration::ZERO;
 
-use serialport::UsbPortInfo;
-use std::path::PathBuf;
+use crate::utils::{compression::Codec, terminal::Color};
+use regex::Regex;
+use serialport::{DataBits, FlowControl, Parity, SerialPortType, StopBits, UsbPortInfo};
+use std::{path::PathBuf, time::Duration};
 
+/// Terminator appended to every line transmitted to a processor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Cr,
+    Lf,
+    CrLf,
+    None,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Cr => "\r",
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::None => "",
+        }
+    }
+}
+
+/// What the console does with ANSI escape sequences in processor output. The
+/// log file always receives them stripped, and they never count toward the
+/// line width.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnsiMode {
+    Strip,
+    /// Forward colors and text styles (SGR) to the terminal, eg to show
+    /// firmware log colors; cursor moves, clears and the like are dropped.
+    #[default]
+    Pass,
+    /// Show them as visible `^[[31m` text.
+    Render,
+}
+
+/// How a processor's received bytes are shown and logged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayMode {
+    #[default]
+    Text,
+    /// Offset/hex/ASCII dump rows, for binary protocols. Typed lines are
+    /// parsed as hex byte strings, eg `\x7e\x01` or `7e 01`.
+    Hex,
+}
+
+/// How a bridged port talks to its TCP clients.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BridgeProtocol {
+    /// Bytes pass through untouched, eg for `nc` or a socat pty.
+    #[default]
+    Raw,
+    /// Telnet framing with the client in character mode and the device
+    /// doing the echo.
+    Telnet,
+}
+
+/// Serves a processor's port to TCP clients alongside the local console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BridgeInfo {
+    pub tcp_port: u16,
+    pub protocol: BridgeProtocol,
+}
+
+/// Sends `response` to the `target` processor whenever `pattern` matches the
+/// output of the processor owning the rule.
+///
+/// The pattern is matched against the last `lines` completed lines joined
+/// with `'\n'`, and `$n` in the response expands to capture group `n`, or
+/// `${n}` when followed by a letter or digit. The response is sent with the
+/// target's `tx_line_ending`.
+///
+/// Configs relying on the watchdog kick that used to be built in for a
+/// processor named `f4` get it back by adding this to that processor:
+///
+/// ```json
+/// "triggers": [{ "pattern": "IPC Comm Failure", "response": "t ipcwdg" }]
+/// ```
+#[derive(Clone, Debug)]
+pub struct TriggerRule {
+    pub pattern: Regex,
+    pub response: String,
+    pub lines: usize,
+    pub target: String,
+}
+
+/// Console lines matching `pattern` are shown in `color`.
+#[derive(Clone, Debug)]
+pub struct HighlightRule {
+    pub pattern: Regex,
+    pub color: Color,
+}
+
+#[derive(Clone)]
 pub struct ProcessorInfo {
     pub port_name: String,
-    pub usb_port_info: UsbPortInfo,
+    /// How the port was enumerated; `Unknown` for on-board UARTs and for
+    /// ports that are not enumerated at all, eg `loop://` or a PTY.
+    pub port_type: SerialPortType,
     pub baud_rate: BuadRate,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub flow_control: FlowControl,
+    /// Pause after every transmitted byte; zero writes at full speed.
+    pub tx_char_delay: Duration,
+    /// Extra pause after every transmitted line ending.
+    pub tx_line_delay: Duration,
+    pub tx_line_ending: LineEnding,
+    /// Console color of this processor's line prefix.
+    pub color: Option<Color>,
+    pub ansi: AnsiMode,
+    pub display: DisplayMode,
+    pub bridge: Option<BridgeInfo>,
     pub processor_name: String,
+    pub triggers: Box<[TriggerRule]>,
 }
 
 impl ProcessorInfo {
-    pub fn new(port_name: String, usb_port_info: UsbPortInfo) -> Self {
+    pub fn new(port_name: String, port_type: SerialPortType) -> Self {
         Self {
             port_name,
-            usb_port_info,
+            port_type,
             baud_rate: DEFAULT_BAUD_RATE,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+            tx_char_delay: DEFAULT_TX_CHAR_DELAY,
+            tx_line_delay: DEFAULT_TX_LINE_DELAY,
+            tx_line_ending: LineEnding::Cr,
+            color: None,
+            ansi: AnsiMode::default(),
+            display: DisplayMode::default(),
+            bridge: None,
             processor_name: String::new(),
+            triggers: Box::new([]),
+        }
+    }
+
+    pub fn usb_port_info(&self) -> Option<&UsbPortInfo> {
+        match &self.port_type {
+            SerialPortType::UsbPort(usb_port_info) => Some(usb_port_info),
+            _ => None,
+        }
+    }
+
+    /// True if `other` is the same physical USB device, even if it came back
+    /// under a different port name after a replug. Other ports only match by
+    /// name.
+    pub fn same_device(&self, other: &ProcessorInfo) -> bool {
+        let (Some(usb), Some(other_usb)) = (self.usb_port_info(), other.usb_port_info()) else {
+            return self.port_name == other.port_name;
+        };
+        match (&usb.serial_number, &other_usb.serial_number) {
+            (Some(serial), Some(other_serial)) => {
+                (usb.vid, usb.pid, serial) == (other_usb.vid, other_usb.pid, other_serial)
+            }
+            _ => (usb.vid, usb.pid) == (other_usb.vid, other_usb.pid),
+        }
+    }
+}
+
+/// When the file logger moves on to a new log segment. Without a size or
+/// interval limit the whole session goes to one file.
+#[derive(Clone, Debug, Default)]
+pub struct LogRotation {
+    /// Bytes per segment.
+    pub max_size: Option<u64>,
+    /// Wall-clock time per segment.
+    pub interval: Option<Duration>,
+    /// Segments kept on disk, the oldest are deleted first; `None` keeps all.
+    pub keep: Option<usize>,
+}
+
+impl LogRotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_size.is_some() || self.interval.is_some()
+    }
+}
+
+/// How the file logger compresses logs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogCompression {
+    #[default]
+    None,
+    /// Each segment is written as `.log.gz`, flushed often enough to read
+    /// back while the session is still running.
+    Gzip,
+    /// Like `Gzip`, as `.log.zst`.
+    Zstd,
+    /// The open segment stays plain text for tailing, closed segments are
+    /// gzipped in the background.
+    Rotated,
+}
+
+impl LogCompression {
+    /// How the open segment is compressed as it is written.
+    pub fn live_codec(self) -> Option<Codec> {
+        match self {
+            LogCompression::Gzip => Some(Codec::Gzip),
+            LogCompression::Zstd => Some(Codec::Zstd),
+            LogCompression::None | LogCompression::Rotated => None,
         }
     }
 }
@@ -29,4 +221,8 @@ pub struct Config {
     pub processors: Box<[ProcessorInfo]>,
     pub project_name: String,
     pub project_path: PathBuf,
+    pub log_dir: PathBuf,
+    pub log_rotation: LogRotation,
+    pub log_compression: LogCompression,
+    pub highlights: Box<[HighlightRule]>,
 }
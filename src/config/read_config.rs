@@ -1,49 +1,547 @@
 use crate::{
-    config::{BuadRate, Config, ProcessorInfo},
+    config::{
+        AnsiMode, BridgeInfo, BridgeProtocol, BuadRate, Config, DisplayMode, HighlightRule,
+        LineEnding, LogCompression, LogRotation, ProcessorInfo, TriggerRule, DEFAULT_TX_CHAR_DELAY,
+        DEFAULT_TX_LINE_DELAY,
+    },
     utils::user_io::{
-        read_and_parse_user_entry, BoxErr, BoxResult, RaisedError,
-        ReadAndParseUserEntryRes,
+        read_and_parse_user_entry, BoxErr, BoxResult, RaisedError, ReadAndParseUserEntryRes,
     },
+    utils::{terminal::Color, transport::is_unlisted_port},
 };
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serialport::{DataBits, FlowControl, Parity, SerialPortType, StopBits, UsbPortInfo};
 use std::{
     fs::{self, DirEntry, File},
     io::BufReader,
     path::PathBuf,
+    time::Duration,
 };
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ConfigDto {
     project_name: String,
     processors: Box<[ProcessorInfoDto]>,
+    #[serde(default)]
+    highlights: Box<[HighlightRuleDto]>,
+    #[serde(default)]
+    log_rotation: LogRotationDto,
+    #[serde(default = "default_log_compression")]
+    log_compression: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LogRotationDto {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_size_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interval_min: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keep: Option<usize>,
+}
+
+pub const BYTES_PER_MB: u64 = 1 << 20;
+
+impl LogRotation {
+    fn to_dto(&self) -> LogRotationDto {
+        LogRotationDto {
+            max_size_mb: self.max_size.map(|size| size.div_ceil(BYTES_PER_MB)),
+            interval_min: self
+                .interval
+                .map(|interval| interval.as_secs().div_ceil(60)),
+            keep: self.keep,
+        }
+    }
+
+    fn from_dto(dto: LogRotationDto) -> BoxResult<Self> {
+        if dto.max_size_mb == Some(0) || dto.interval_min == Some(0) || dto.keep == Some(0) {
+            return Err(RaisedError::new("log_rotation limits must be above 0"));
+        }
+        let too_large = || RaisedError::new("log_rotation limits are too large");
+        let max_size = dto.max_size_mb.map(|size| size.checked_mul(BYTES_PER_MB));
+        let interval = dto.interval_min.map(|min| min.checked_mul(60));
+        let max_size = max_size
+            .map(|size| size.ok_or_else(too_large))
+            .transpose()?;
+        let interval = interval
+            .map(|secs| secs.ok_or_else(too_large))
+            .transpose()?;
+        Ok(Self {
+            max_size,
+            interval: interval.map(Duration::from_secs),
+            keep: dto.keep,
+        })
+    }
+}
+
+fn default_log_compression() -> String {
+    String::from("none")
+}
+
+pub fn log_compression_from_str(compression: &str) -> BoxResult<LogCompression> {
+    match compression {
+        "none" => Ok(LogCompression::None),
+        "gzip" => Ok(LogCompression::Gzip),
+        "zstd" => Ok(LogCompression::Zstd),
+        "rotated" => Ok(LogCompression::Rotated),
+        _ => Err(RaisedError::new(
+            "log_compression must be none, gzip, zstd or rotated",
+        )),
+    }
+}
+
+pub fn log_compression_to_str(compression: LogCompression) -> &'static str {
+    match compression {
+        LogCompression::None => "none",
+        LogCompression::Gzip => "gzip",
+        LogCompression::Zstd => "zstd",
+        LogCompression::Rotated => "rotated",
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HighlightRuleDto {
+    pattern: String,
+    color: String,
+}
+
+impl HighlightRule {
+    fn to_dto(&self) -> HighlightRuleDto {
+        HighlightRuleDto {
+            pattern: self.pattern.as_str().to_string(),
+            color: self.color.name(),
+        }
+    }
+
+    fn from_dto(dto: HighlightRuleDto) -> BoxResult<Self> {
+        Ok(Self {
+            pattern: Regex::new(&dto.pattern).box_err()?,
+            color: color_from_str(&dto.color)?,
+        })
+    }
+}
+
+pub fn color_from_str(color: &str) -> BoxResult<Color> {
+    Color::from_name(color).ok_or_else(|| RaisedError::new("unknown color"))
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProcessorInfoDto {
     processor_name: String,
     baud_rate: BuadRate,
+    #[serde(default = "default_data_bits")]
+    data_bits: u8,
+    #[serde(default = "default_parity")]
+    parity: String,
+    #[serde(default = "default_stop_bits")]
+    stop_bits: u8,
+    #[serde(default = "default_flow_control")]
+    flow_control: String,
+    #[serde(default = "default_tx_char_delay_us")]
+    tx_char_delay_us: u64,
+    #[serde(default = "default_tx_line_delay_us")]
+    tx_line_delay_us: u64,
+    #[serde(default = "default_tx_line_ending")]
+    tx_line_ending: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(default = "default_ansi")]
+    ansi: String,
+    #[serde(default = "default_display")]
+    display: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bridge: Option<BridgeDto>,
     port_name: String,
+    /// Inferred from `vid` and `pid` in configs saved without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vid: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pid: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    serial_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manufacturer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    product: Option<String>,
+    #[serde(default)]
+    triggers: Box<[TriggerRuleDto]>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BridgeDto {
+    tcp_port: u16,
+    #[serde(default = "default_bridge_protocol")]
+    protocol: String,
+}
+
+fn default_bridge_protocol() -> String {
+    String::from("raw")
+}
+
+pub fn bridge_protocol_from_str(protocol: &str) -> BoxResult<BridgeProtocol> {
+    match protocol {
+        "raw" => Ok(BridgeProtocol::Raw),
+        "telnet" => Ok(BridgeProtocol::Telnet),
+        _ => Err(RaisedError::new("bridge protocol must be raw or telnet")),
+    }
+}
+
+pub fn bridge_protocol_to_str(protocol: BridgeProtocol) -> &'static str {
+    match protocol {
+        BridgeProtocol::Raw => "raw",
+        BridgeProtocol::Telnet => "telnet",
+    }
+}
+
+impl BridgeInfo {
+    fn to_dto(self) -> BridgeDto {
+        BridgeDto {
+            tcp_port: self.tcp_port,
+            protocol: bridge_protocol_to_str(self.protocol).to_string(),
+        }
+    }
+
+    fn from_dto(dto: &BridgeDto) -> BoxResult<Self> {
+        Ok(Self {
+            tcp_port: dto.tcp_port,
+            protocol: bridge_protocol_from_str(&dto.protocol)?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TriggerRuleDto {
+    pattern: String,
+    response: String,
+    #[serde(default = "default_trigger_lines")]
+    lines: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+fn default_trigger_lines() -> usize {
+    1
+}
+
+fn default_data_bits() -> u8 {
+    8
+}
+
+fn default_parity() -> String {
+    String::from("none")
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
+fn default_flow_control() -> String {
+    String::from("none")
+}
+
+fn default_tx_char_delay_us() -> u64 {
+    DEFAULT_TX_CHAR_DELAY.as_micros() as u64
+}
+
+fn default_tx_line_delay_us() -> u64 {
+    DEFAULT_TX_LINE_DELAY.as_micros() as u64
+}
+
+fn default_tx_line_ending() -> String {
+    String::from("cr")
+}
+
+fn default_ansi() -> String {
+    String::from("pass")
+}
+
+pub fn ansi_mode_from_str(ansi: &str) -> BoxResult<AnsiMode> {
+    match ansi {
+        "strip" => Ok(AnsiMode::Strip),
+        "pass" => Ok(AnsiMode::Pass),
+        "render" => Ok(AnsiMode::Render),
+        _ => Err(RaisedError::new("ansi must be strip, pass or render")),
+    }
+}
+
+pub fn ansi_mode_to_str(ansi: AnsiMode) -> &'static str {
+    match ansi {
+        AnsiMode::Strip => "strip",
+        AnsiMode::Pass => "pass",
+        AnsiMode::Render => "render",
+    }
+}
+
+fn default_display() -> String {
+    String::from("text")
+}
+
+pub fn display_mode_from_str(display: &str) -> BoxResult<DisplayMode> {
+    match display {
+        "text" => Ok(DisplayMode::Text),
+        "hex" => Ok(DisplayMode::Hex),
+        _ => Err(RaisedError::new("display must be text or hex")),
+    }
+}
+
+pub fn display_mode_to_str(display: DisplayMode) -> &'static str {
+    match display {
+        DisplayMode::Text => "text",
+        DisplayMode::Hex => "hex",
+    }
+}
+
+pub fn line_ending_from_str(line_ending: &str) -> BoxResult<LineEnding> {
+    match line_ending {
+        "cr" => Ok(LineEnding::Cr),
+        "lf" => Ok(LineEnding::Lf),
+        "crlf" => Ok(LineEnding::CrLf),
+        "none" => Ok(LineEnding::None),
+        _ => Err(RaisedError::new(
+            "tx_line_ending must be cr, lf, crlf or none",
+        )),
+    }
+}
+
+pub fn line_ending_to_str(line_ending: LineEnding) -> &'static str {
+    match line_ending {
+        LineEnding::Cr => "cr",
+        LineEnding::Lf => "lf",
+        LineEnding::CrLf => "crlf",
+        LineEnding::None => "none",
+    }
+}
+
+pub fn data_bits_from_u8(data_bits: u8) -> BoxResult<DataBits> {
+    match data_bits {
+        5 => Ok(DataBits::Five),
+        6 => Ok(DataBits::Six),
+        7 => Ok(DataBits::Seven),
+        8 => Ok(DataBits::Eight),
+        _ => Err(RaisedError::new("data_bits must be 5, 6, 7 or 8")),
+    }
+}
+
+pub fn data_bits_to_u8(data_bits: DataBits) -> u8 {
+    match data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    }
+}
+
+pub fn parity_from_str(parity: &str) -> BoxResult<Parity> {
+    match parity {
+        "none" | "n" => Ok(Parity::None),
+        "odd" | "o" => Ok(Parity::Odd),
+        "even" | "e" => Ok(Parity::Even),
+        _ => Err(RaisedError::new("parity must be none, odd or even")),
+    }
+}
+
+pub fn parity_to_str(parity: Parity) -> &'static str {
+    match parity {
+        Parity::None => "none",
+        Parity::Odd => "odd",
+        Parity::Even => "even",
+    }
+}
+
+pub fn stop_bits_from_u8(stop_bits: u8) -> BoxResult<StopBits> {
+    match stop_bits {
+        1 => Ok(StopBits::One),
+        2 => Ok(StopBits::Two),
+        _ => Err(RaisedError::new("stop_bits must be 1 or 2")),
+    }
+}
+
+pub fn stop_bits_to_u8(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+pub fn flow_control_from_str(flow_control: &str) -> BoxResult<FlowControl> {
+    match flow_control {
+        "none" => Ok(FlowControl::None),
+        "software" => Ok(FlowControl::Software),
+        "hardware" => Ok(FlowControl::Hardware),
+        _ => Err(RaisedError::new(
+            "flow_control must be none, software or hardware",
+        )),
+    }
+}
+
+pub fn flow_control_to_str(flow_control: FlowControl) -> &'static str {
+    match flow_control {
+        FlowControl::None => "none",
+        FlowControl::Software => "software",
+        FlowControl::Hardware => "hardware",
+    }
+}
+
+pub fn port_type_to_str(port_type: &SerialPortType) -> &'static str {
+    match port_type {
+        SerialPortType::UsbPort(_) => "usb",
+        SerialPortType::PciPort => "pci",
+        SerialPortType::BluetoothPort => "bluetooth",
+        SerialPortType::Unknown => "unknown",
+    }
+}
+
+impl TriggerRule {
+    fn to_dto(&self, processor_name: &str) -> TriggerRuleDto {
+        TriggerRuleDto {
+            pattern: self.pattern.as_str().to_string(),
+            response: self.response.clone(),
+            lines: self.lines,
+            target: Some(self.target.clone()).filter(|target| target != processor_name),
+        }
+    }
+
+    fn from_dto(dto: TriggerRuleDto, processor_name: &str) -> BoxResult<Self> {
+        if dto.lines == 0 {
+            return Err(RaisedError::new("trigger must match at least 1 line"));
+        }
+        Ok(Self {
+            pattern: Regex::new(&dto.pattern).box_err()?,
+            response: dto.response,
+            lines: dto.lines,
+            target: dto.target.unwrap_or_else(|| processor_name.to_string()),
+        })
+    }
 }
 
 impl ProcessorInfo {
     fn to_dto(&self) -> ProcessorInfoDto {
+        let usb_field = |field: fn(&UsbPortInfo) -> &Option<String>| {
+            self.usb_port_info().and_then(|usb| field(usb).clone())
+        };
         ProcessorInfoDto {
             processor_name: self.processor_name.clone(),
             baud_rate: self.baud_rate,
+            data_bits: data_bits_to_u8(self.data_bits),
+            parity: parity_to_str(self.parity).to_string(),
+            stop_bits: stop_bits_to_u8(self.stop_bits),
+            flow_control: flow_control_to_str(self.flow_control).to_string(),
+            tx_char_delay_us: self.tx_char_delay.as_micros() as u64,
+            tx_line_delay_us: self.tx_line_delay.as_micros() as u64,
+            tx_line_ending: line_ending_to_str(self.tx_line_ending).to_string(),
+            color: self.color.map(Color::name),
+            ansi: ansi_mode_to_str(self.ansi).to_string(),
+            display: display_mode_to_str(self.display).to_string(),
+            bridge: self.bridge.map(BridgeInfo::to_dto),
             port_name: self.port_name.clone(),
+            port_type: Some(port_type_to_str(&self.port_type).to_string()),
+            vid: self.usb_port_info().map(|usb| usb.vid),
+            pid: self.usb_port_info().map(|usb| usb.pid),
+            serial_number: usb_field(|usb| &usb.serial_number),
+            manufacturer: usb_field(|usb| &usb.manufacturer),
+            product: usb_field(|usb| &usb.product),
+            triggers: self
+                .triggers
+                .iter()
+                .map(|t| t.to_dto(&self.processor_name))
+                .collect(),
         }
     }
 }
 
 impl ProcessorInfo {
-    pub fn duplicate_from_dto(&self, dto: ProcessorInfoDto) -> Self {
-        Self {
+    pub fn duplicate_from_dto(&self, dto: ProcessorInfoDto) -> BoxResult<Self> {
+        let triggers = dto
+            .triggers
+            .into_vec()
+            .into_iter()
+            .map(|t| TriggerRule::from_dto(t, &dto.processor_name))
+            .collect::<BoxResult<_>>()?;
+        Ok(Self {
             port_name: self.port_name.clone(),
-            usb_port_info: self.usb_port_info.clone(),
+            port_type: self.port_type.clone(),
             baud_rate: dto.baud_rate,
+            data_bits: data_bits_from_u8(dto.data_bits)?,
+            parity: parity_from_str(&dto.parity)?,
+            stop_bits: stop_bits_from_u8(dto.stop_bits)?,
+            flow_control: flow_control_from_str(&dto.flow_control)?,
+            tx_char_delay: Duration::from_micros(dto.tx_char_delay_us),
+            tx_line_delay: Duration::from_micros(dto.tx_line_delay_us),
+            tx_line_ending: line_ending_from_str(&dto.tx_line_ending)?,
+            color: dto.color.as_deref().map(color_from_str).transpose()?,
+            ansi: ansi_mode_from_str(&dto.ansi)?,
+            display: display_mode_from_str(&dto.display)?,
+            bridge: dto.bridge.as_ref().map(BridgeInfo::from_dto).transpose()?,
             processor_name: dto.processor_name,
+            triggers,
+        })
+    }
+}
+
+impl ProcessorInfoDto {
+    /// How precisely the saved identity pins down a device: serial number,
+    /// then vid/pid, then only the port name.
+    fn specificity(&self) -> u8 {
+        match (self.vid.zip(self.pid), &self.serial_number) {
+            (Some(_), Some(_)) => 2,
+            (Some(_), None) => 1,
+            (None, _) => 0,
         }
     }
+
+    /// The device as it was when the config was saved.
+    fn saved_processor(&self) -> ProcessorInfo {
+        let usb_port_info = self.vid.zip(self.pid).map(|(vid, pid)| UsbPortInfo {
+            vid,
+            pid,
+            serial_number: self.serial_number.clone(),
+            manufacturer: self.manufacturer.clone(),
+            product: self.product.clone(),
+        });
+        let port_type = match (self.port_type.as_deref(), usb_port_info) {
+            (Some("pci"), _) => SerialPortType::PciPort,
+            (Some("bluetooth"), _) => SerialPortType::BluetoothPort,
+            (Some("usb") | None, Some(usb_port_info)) => SerialPortType::UsbPort(usb_port_info),
+            _ => SerialPortType::Unknown,
+        };
+        ProcessorInfo::new(self.port_name.clone(), port_type)
+    }
+
+    /// `None` if `p` cannot be the saved device, otherwise a rank where an
+    /// unchanged port name wins ties between identical boards.
+    fn match_rank(&self, p: &ProcessorInfo) -> Option<u8> {
+        let same_port_name = u8::from(p.port_name == self.port_name);
+        let usb = p.usb_port_info();
+        let serial_number = usb.and_then(|usb| usb.serial_number.as_ref());
+        match self.vid.zip(self.pid) {
+            None if same_port_name == 1 => Some(0),
+            None => None,
+            Some(vid_pid) if Some(vid_pid) != usb.map(|usb| (usb.vid, usb.pid)) => None,
+            Some(_)
+                if self.serial_number.is_some() && self.serial_number.as_ref() != serial_number =>
+            {
+                None
+            }
+            Some(_) => Some(same_port_name),
+        }
+    }
+}
+
+/// The first processor name used twice; trigger targets and write routes
+/// look processors up by name, so names must be unique.
+pub fn duplicate_processor_name(processors: &[ProcessorInfo]) -> Option<&str> {
+    processors
+        .iter()
+        .enumerate()
+        .find(|(idx, p)| {
+            processors[..*idx]
+                .iter()
+                .any(|q| q.processor_name == p.processor_name)
+        })
+        .map(|(_, p)| p.processor_name.as_str())
 }
 
 impl Config {
@@ -52,34 +550,72 @@ impl Config {
         cfg: ConfigDto,
         proc_info: &[ProcessorInfo],
     ) -> BoxResult<Self> {
-        let mut processors = vec![];
         let cfg_processors_len = cfg.processors.len();
-        for p_dto in cfg.processors.into_vec() {
-            for p_rc in proc_info {
-                if p_rc.port_name.eq(&p_dto.port_name) {
-                    processors.push(p_rc.duplicate_from_dto(p_dto));
-                    break;
+        let mut p_dtos: Vec<_> = cfg.processors.into_vec().into_iter().enumerate().collect();
+        p_dtos.sort_by_key(|(_, p_dto)| std::cmp::Reverse(p_dto.specificity()));
+
+        let mut claimed = vec![false; proc_info.len()];
+        let mut resolved = vec![];
+        for (dto_idx, p_dto) in p_dtos {
+            let best = proc_info
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| !claimed[*idx])
+                .filter_map(|(idx, p_rc)| Some((p_dto.match_rank(p_rc)?, idx)))
+                .max_by_key(|(rank, idx)| (*rank, std::cmp::Reverse(*idx)));
+            if let Some((_, idx)) = best {
+                claimed[idx] = true;
+                let p_rc = &proc_info[idx];
+                if p_rc.port_name != p_dto.port_name {
+                    println!(
+                        "> [config] {} moved from {} to {}",
+                        p_dto.processor_name, p_dto.port_name, p_rc.port_name
+                    );
                 }
+                resolved.push((dto_idx, p_rc.duplicate_from_dto(p_dto)?));
+            } else if p_dto.specificity() == 0 && is_unlisted_port(&p_dto.port_name) {
+                let p_rc = p_dto.saved_processor();
+                resolved.push((dto_idx, p_rc.duplicate_from_dto(p_dto)?));
             }
         }
+        resolved.sort_by_key(|(dto_idx, _)| *dto_idx);
+        let processors: Vec<_> = resolved.into_iter().map(|(_, p)| p).collect();
 
-        if processors.len() == cfg_processors_len {
+        let is_target = |t: &TriggerRule| processors.iter().any(|p| p.processor_name == t.target);
+        if processors.len() != cfg_processors_len {
+            Err(RaisedError::new("port not found"))
+        } else if let Some(name) = duplicate_processor_name(&processors) {
+            Err(RaisedError::new(&format!(
+                "duplicate processor_name {:?}",
+                name
+            )))
+        } else if processors.iter().any(|p| !p.triggers.iter().all(is_target)) {
+            Err(RaisedError::new("trigger target not found"))
+        } else {
+            let highlights = cfg.highlights.into_vec().into_iter();
             Ok(Self {
                 processors: processors.into(),
+                highlights: highlights
+                    .map(HighlightRule::from_dto)
+                    .collect::<BoxResult<_>>()?,
+                log_dir: PathBuf::from(&cfg.project_name),
+                log_rotation: LogRotation::from_dto(cfg.log_rotation)?,
+                log_compression: log_compression_from_str(&cfg.log_compression)?,
                 project_name: cfg.project_name,
                 project_path,
             })
-        } else {
-            Err(RaisedError::new("port not found"))
         }
     }
 }
 
 impl Config {
     pub fn save_config_file(self) -> Self {
-        let ref value = ConfigDto {
+        let value = &ConfigDto {
             project_name: self.project_name.clone(),
             processors: self.processors.iter().map(|p| p.to_dto()).collect(),
+            highlights: self.highlights.iter().map(|h| h.to_dto()).collect(),
+            log_rotation: self.log_rotation.to_dto(),
+            log_compression: log_compression_to_str(self.log_compression).to_string(),
         };
 
         let contents = serde_json::to_string_pretty(value).unwrap();
@@ -89,14 +625,14 @@ impl Config {
 }
 
 impl Config {
-    pub fn read_config_file(
-        dir_entry: DirEntry,
-        procs: &[ProcessorInfo],
-    ) -> BoxResult<Config> {
+    pub fn read_config_file(dir_entry: DirEntry, procs: &[ProcessorInfo]) -> BoxResult<Config> {
         if dir_entry.file_type().box_err()?.is_dir() {
             return Err(RaisedError::new("path to dir"));
         }
-        let project_path = dir_entry.path();
+        Self::read_config_path(dir_entry.path(), procs)
+    }
+
+    pub fn read_config_path(project_path: PathBuf, procs: &[ProcessorInfo]) -> BoxResult<Config> {
         match project_path.extension() {
             Some(ext) if ext == "json" => {
                 let file = File::open(&project_path).box_err()?;
@@ -110,6 +646,15 @@ impl Config {
             _ => Err(RaisedError::new("bad ext")),
         }
     }
+
+    /// Reads a config without any hardware attached, every processor keeping
+    /// the port it was saved with. Used to replay sessions.
+    pub fn read_config_offline(project_path: PathBuf) -> BoxResult<Config> {
+        let file = File::open(&project_path).box_err()?;
+        let cfg: ConfigDto = serde_json::from_reader(BufReader::new(file)).box_err()?;
+        let procs: Vec<_> = cfg.processors.iter().map(|p| p.saved_processor()).collect();
+        Config::from_dto(project_path, cfg, &procs)
+    }
 }
 
 pub enum UserSelectFileRes {
@@ -123,12 +668,9 @@ impl Config {
     pub fn user_select_file(procs: &[ProcessorInfo]) -> UserSelectFileRes {
         let mut config_vec = vec![];
         let paths = fs::read_dir("./config").unwrap();
-        for dir_entry_res in paths {
-            if let Ok(dir_entry) = dir_entry_res {
-                match Self::read_config_file(dir_entry, &procs) {
-                    Ok(cfg) => config_vec.push(cfg),
-                    _ => {}
-                }
+        for dir_entry in paths.flatten() {
+            if let Ok(cfg) = Self::read_config_file(dir_entry, procs) {
+                config_vec.push(cfg);
             }
         }
         if config_vec.is_empty() {
@@ -173,3 +715,153 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn usb(port_name: &str, vid: u16, pid: u16, serial_number: Option<&str>) -> ProcessorInfo {
+        let usb_port_info = UsbPortInfo {
+            vid,
+            pid,
+            serial_number: serial_number.map(String::from),
+            manufacturer: None,
+            product: None,
+        };
+        ProcessorInfo::new(port_name.into(), SerialPortType::UsbPort(usb_port_info))
+    }
+
+    fn config(processors: Value, procs: &[ProcessorInfo]) -> BoxResult<Config> {
+        let dto = json!({ "project_name": "test", "processors": processors });
+        let dto = serde_json::from_value(dto).unwrap();
+        Config::from_dto(PathBuf::from("test.json"), dto, procs)
+    }
+
+    fn resolved(cfg: &Config) -> Vec<(&str, &str)> {
+        cfg.processors
+            .iter()
+            .map(|p| (p.processor_name.as_str(), p.port_name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn tells_identical_boards_apart_by_serial_number() {
+        let processors = json!([
+            { "processor_name": "a", "baud_rate": 115200, "port_name": "/dev/ttyUSB0",
+              "vid": 0x0403, "pid": 0x6001, "serial_number": "AAA" },
+            { "processor_name": "b", "baud_rate": 115200, "port_name": "/dev/ttyUSB1",
+              "vid": 0x0403, "pid": 0x6001, "serial_number": "BBB" },
+        ]);
+        let procs = [
+            usb("/dev/ttyUSB0", 0x0403, 0x6001, Some("BBB")),
+            usb("/dev/ttyUSB1", 0x0403, 0x6001, Some("AAA")),
+        ];
+        let cfg = config(processors, &procs).unwrap();
+        assert_eq!(
+            resolved(&cfg),
+            [("a", "/dev/ttyUSB1"), ("b", "/dev/ttyUSB0")]
+        );
+    }
+
+    #[test]
+    fn follows_swapped_port_names() {
+        let processors = json!([
+            { "processor_name": "ftdi", "baud_rate": 115200, "port_name": "/dev/ttyUSB0",
+              "vid": 0x0403, "pid": 0x6001 },
+            { "processor_name": "cp210x", "baud_rate": 9600, "port_name": "/dev/ttyUSB1",
+              "vid": 0x10c4, "pid": 0xea60 },
+        ]);
+        let procs = [
+            usb("/dev/ttyUSB0", 0x10c4, 0xea60, None),
+            usb("/dev/ttyUSB1", 0x0403, 0x6001, None),
+        ];
+        let cfg = config(processors, &procs).unwrap();
+        assert_eq!(
+            resolved(&cfg),
+            [("ftdi", "/dev/ttyUSB1"), ("cp210x", "/dev/ttyUSB0")]
+        );
+        assert_eq!(cfg.processors[1].baud_rate, 9600);
+    }
+
+    #[test]
+    fn reports_a_processor_without_a_port() {
+        let processors = json!([
+            { "processor_name": "a", "baud_rate": 115200, "port_name": "/dev/ttyUSB0",
+              "vid": 0x0403, "pid": 0x6001, "serial_number": "AAA" },
+        ]);
+        let procs = [usb("/dev/ttyUSB0", 0x0403, 0x6001, Some("BBB"))];
+        assert!(config(processors.clone(), &procs).is_err());
+        assert!(config(processors, &[]).is_err());
+    }
+
+    #[test]
+    fn most_specific_match_claims_its_board_first() {
+        // the vid/pid-only entry comes first and is saved on the port the
+        // serial numbered board is on now, yet it must not take that board
+        let processors = json!([
+            { "processor_name": "any", "baud_rate": 115200, "port_name": "/dev/ttyUSB0",
+              "vid": 0x0403, "pid": 0x6001 },
+            { "processor_name": "pinned", "baud_rate": 115200, "port_name": "/dev/ttyUSB1",
+              "vid": 0x0403, "pid": 0x6001, "serial_number": "PIN" },
+        ]);
+        let procs = [
+            usb("/dev/ttyUSB0", 0x0403, 0x6001, Some("PIN")),
+            usb("/dev/ttyUSB1", 0x0403, 0x6001, Some("OTHER")),
+        ];
+        let cfg = config(processors, &procs).unwrap();
+        assert_eq!(
+            resolved(&cfg),
+            [("any", "/dev/ttyUSB1"), ("pinned", "/dev/ttyUSB0")]
+        );
+    }
+
+    #[test]
+    fn rejects_log_rotation_limits_that_overflow() {
+        let dto = |max_size_mb, interval_min| LogRotationDto {
+            max_size_mb,
+            interval_min,
+            keep: None,
+        };
+        assert!(LogRotation::from_dto(dto(Some(u64::MAX / 2), None)).is_err());
+        assert!(LogRotation::from_dto(dto(None, Some(u64::MAX / 2))).is_err());
+        let rotation = LogRotation::from_dto(dto(Some(3), Some(2))).unwrap();
+        assert_eq!(rotation.max_size, Some(3 * BYTES_PER_MB));
+        assert_eq!(rotation.interval, Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn parses_line_settings_fields() {
+        let processors = json!([
+            { "processor_name": "a", "baud_rate": 9600, "port_name": "/dev/ttyS0",
+              "data_bits": 7, "parity": "even", "stop_bits": 2, "flow_control": "hardware" },
+        ]);
+        let procs = [ProcessorInfo::new(
+            "/dev/ttyS0".into(),
+            SerialPortType::Unknown,
+        )];
+        let cfg = config(processors, &procs).unwrap();
+        let p = &cfg.processors[0];
+        assert_eq!(
+            (p.data_bits, p.parity, p.stop_bits, p.flow_control),
+            (
+                DataBits::Seven,
+                Parity::Even,
+                StopBits::Two,
+                FlowControl::Hardware
+            )
+        );
+
+        assert_eq!(parity_from_str("o").unwrap(), Parity::Odd);
+        assert_eq!(
+            flow_control_from_str("software").unwrap(),
+            FlowControl::Software
+        );
+        assert!(data_bits_from_u8(9).is_err());
+        assert!(data_bits_from_u8(4).is_err());
+        assert!(parity_from_str("mark").is_err());
+        assert!(stop_bits_from_u8(3).is_err());
+        assert!(stop_bits_from_u8(0).is_err());
+        assert!(flow_control_from_str("rts").is_err());
+    }
+}
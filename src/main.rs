@@ -4,8 +4,15 @@
 // 110, 300, 600, 1200, 2400, 4800, 9600, 14400,
 // 19200, 38400, 57600, 115200, 128000 and 256000 (we use 3000000)
 
-use jfconsole::threads::main_thread::main_task;
+use jfconsole::{
+    config::cli_args::{CliArgs, USAGE},
+    threads::main_thread::main_task,
+    utils::user_io::box_err_msg,
+};
 
 pub fn main() {
-    main_task()
+    match CliArgs::from_env() {
+        Ok(cli_args) => main_task(cli_args),
+        Err(e) => println!("{}\n\n{}", box_err_msg(&e), USAGE),
+    }
 }
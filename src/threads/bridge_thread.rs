@@ -0,0 +1,228 @@
+use crate::{
+    config::{BridgeInfo, BridgeProtocol, ProcessorInfo},
+    threads::serial_console_thread::{IDLE_POLL_PERIOD, WRITE_RETRY_PERIOD},
+    utils::{
+        console_output::ConsoleOutput,
+        line_printer::LinePrinter,
+        ring_buf_queue::{RingBufQConsumer, RingBufQProducer},
+        socket_buffer::SocketBuffer,
+        sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
+        telnet::{
+            escape_iac, negotiate, TelnetCommand, TelnetParser, DO, DONT, OPT_ECHO,
+            OPT_SUPPRESS_GO_AHEAD, WILL, WONT,
+        },
+        user_io::{BoxErr, BoxResult},
+        waker::{wait_readable, AsRawFd, WakeReceiver, Waker},
+    },
+};
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+};
+
+/// Bridges only take local clients unless `--bridge-bind` exposes them, eg
+/// on `0.0.0.0`; anyone who can connect can type into the port.
+pub const DEFAULT_BRIDGE_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Serves one processor's port on a TCP socket. Every client gets a copy of
+/// the received bytes, and whatever clients send is queued for the port
+/// beside the local console's writes.
+pub struct BridgeThread<const SIZE: usize> {
+    assassin: SyncFlagAssassin,
+    waker: Waker,
+    join_handle: JoinHandle<BoxResult<()>>,
+}
+
+impl<const SIZE: usize> BridgeThread<SIZE> {
+    /// `rx_consumer` carries the port's received bytes, `write_producer`
+    /// queues client writes for the port and `write_printer` logs them.
+    pub fn spawn(
+        processor_info: &ProcessorInfo,
+        bridge: BridgeInfo,
+        bind_addr: IpAddr,
+        rx_consumer: RingBufQConsumer<SIZE, u8>,
+        (waker, wake_receiver): (Waker, WakeReceiver),
+        client_writes: (RingBufQProducer<u8>, LinePrinter),
+        status: ConsoleOutput,
+    ) -> BoxResult<Self> {
+        let listener = TcpListener::bind((bind_addr, bridge.tcp_port)).box_err()?;
+        listener.set_nonblocking(true).box_err()?;
+        println!(
+            "> [bridge_task] serving {} on {} ({:?})",
+            processor_info.processor_name,
+            listener.local_addr().box_err()?,
+            bridge.protocol
+        );
+        let (victim, assassin) = new_sync_flag();
+
+        Ok(Self {
+            assassin,
+            waker,
+            join_handle: thread::spawn(move || {
+                bridge_task(
+                    victim,
+                    listener,
+                    bridge.protocol,
+                    rx_consumer,
+                    wake_receiver,
+                    client_writes,
+                    status,
+                )
+            }),
+        })
+    }
+
+    pub fn join(self) -> BoxResult<()> {
+        self.assassin.kill_victim();
+        self.waker.wake();
+        self.join_handle.join()?
+    }
+}
+
+struct BridgeClient {
+    socket: SocketBuffer<TcpStream>,
+    addr: SocketAddr,
+    /// `None` for raw clients.
+    telnet: Option<TelnetParser>,
+}
+
+impl BridgeClient {
+    fn new(stream: TcpStream, addr: SocketAddr, protocol: BridgeProtocol) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        let mut client = Self {
+            socket: SocketBuffer::new(stream),
+            addr,
+            telnet: None,
+        };
+        if protocol == BridgeProtocol::Telnet {
+            client.telnet = Some(TelnetParser::new(true));
+            // character mode: the client sends each key, the device echoes
+            for (verb, option) in [
+                (WILL, OPT_ECHO),
+                (WILL, OPT_SUPPRESS_GO_AHEAD),
+                (DO, OPT_SUPPRESS_GO_AHEAD),
+            ] {
+                client.socket.push(&negotiate(verb, option));
+            }
+        }
+        Ok(client)
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        match self.telnet {
+            Some(_) => {
+                let mut escaped = vec![];
+                escape_iac(bytes, &mut escaped);
+                self.socket.push(&escaped);
+            }
+            None => self.socket.push(bytes),
+        }
+    }
+
+    /// Reads everything available, returning the data bytes.
+    fn receive(&mut self) -> Vec<u8> {
+        let mut input = vec![];
+        self.socket.read_available(&mut input);
+        let Some(telnet) = &mut self.telnet else {
+            return input;
+        };
+        let mut data = vec![];
+        for command in telnet.parse(&input, &mut data) {
+            // refuse every option but the ones offered on connect
+            let reply = match command {
+                TelnetCommand::Negotiate { verb: DO, option }
+                    if option != OPT_ECHO && option != OPT_SUPPRESS_GO_AHEAD =>
+                {
+                    negotiate(WONT, option)
+                }
+                TelnetCommand::Negotiate { verb: WILL, option }
+                    if option != OPT_SUPPRESS_GO_AHEAD =>
+                {
+                    negotiate(DONT, option)
+                }
+                _ => continue,
+            };
+            self.socket.push(&reply);
+        }
+        data
+    }
+}
+
+fn bridge_task<const SIZE: usize>(
+    victim: SyncFlagVictim,
+    listener: TcpListener,
+    protocol: BridgeProtocol,
+    mut rx_consumer: RingBufQConsumer<SIZE, u8>,
+    wake_receiver: WakeReceiver,
+    (mut write_producer, mut write_printer): (RingBufQProducer<u8>, LinePrinter),
+    status: ConsoleOutput,
+) -> BoxResult<()> {
+    let mut clients: Vec<BridgeClient> = vec![];
+
+    while victim.is_alive() {
+        loop {
+            let bytes = rx_consumer.pop();
+            if bytes.is_empty() {
+                break;
+            }
+            for client in clients.iter_mut() {
+                client.send(&bytes);
+            }
+        }
+        for client in clients.iter_mut() {
+            client.socket.flush();
+        }
+
+        let mut fds = vec![listener.as_raw_fd(), wake_receiver.as_raw_fd()];
+        fds.extend(clients.iter().map(|client| client.socket.as_raw_fd()));
+        let timeout = match clients.iter().all(|client| client.socket.is_flushed()) {
+            true => IDLE_POLL_PERIOD,
+            false => WRITE_RETRY_PERIOD,
+        };
+        let ready = wait_readable(&fds, timeout).box_err()?;
+        if ready[1] {
+            wake_receiver.drain();
+        }
+
+        for (client, _) in clients.iter_mut().zip(&ready[2..]).filter(|(_, r)| **r) {
+            let data = client.receive();
+            if !data.is_empty() {
+                write_printer.push_bytes(&data);
+                write_producer.push(data);
+            }
+        }
+        clients.retain(|client| {
+            if client.socket.closed {
+                write_printer.push_marker(&format!("> [bridge_task] {} disconnected", client.addr));
+            }
+            !client.socket.closed
+        });
+
+        if ready[0] {
+            loop {
+                match listener.accept() {
+                    Ok((stream, addr)) => match BridgeClient::new(stream, addr, protocol) {
+                        Ok(client) => {
+                            write_printer
+                                .push_marker(&format!("> [bridge_task] {} connected", addr));
+                            clients.push(client);
+                        }
+                        Err(e) => {
+                            status.print(format!("> [bridge_task] {} setup failed {:?}", addr, e))
+                        }
+                    },
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        status.print(format!("> [bridge_task] accept failed {:?}", e));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::{
+    threads::file_logger_thread::FILE_NAME_DATE_FMT,
+    utils::{
+        capture::{CaptureRecord, RecordKind, CAPTURE_MAGIC},
+        console_output::ConsoleOutput,
+        sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
+        user_io::{BoxErr, BoxResult},
+    },
+};
+use chrono::Utc;
+use std::{
+    fs::{create_dir_all, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long buffered records may sit before they are flushed to disk.
+pub const CAPTURE_FLUSH_PERIOD: Duration = Duration::from_millis(500);
+
+/// Writes every rx/tx chunk of every processor to `<project>_<timestamp>.cap`
+/// next to the line log, see `utils::capture` for the format.
+pub struct CaptureThread {
+    assassin: SyncFlagAssassin,
+    join_handle: JoinHandle<BoxResult<()>>,
+}
+
+impl CaptureThread {
+    pub fn spawn(
+        log_dir: &Path,
+        project_name: &str,
+        processor_names: &[String],
+        capture_receiver: Receiver<CaptureRecord>,
+        status: ConsoleOutput,
+    ) -> BoxResult<Self> {
+        let _ = create_dir_all(log_dir);
+        let file_name = format!(
+            "{}_{}.cap",
+            project_name,
+            Utc::now().format(FILE_NAME_DATE_FMT)
+        );
+        let file_path = log_dir.join(file_name);
+        let file = match OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&file_path)
+        {
+            Ok(opened_file) => {
+                println!("> [capture_task] opened {:?}", file_path);
+                opened_file
+            }
+            Err(e) => {
+                println!("> [capture_task] error {:?}", e);
+                return Err(Box::new(e));
+            }
+        };
+        let mut writer = BufWriter::new(file);
+        writer.write_all(&CAPTURE_MAGIC).box_err()?;
+        for (idx, name) in processor_names.iter().enumerate() {
+            let idx = u8::try_from(idx).box_err()?;
+            CaptureRecord::new(RecordKind::Name, idx, name.as_bytes())
+                .write_to(&mut writer)
+                .box_err()?;
+        }
+
+        let (victim, assassin) = new_sync_flag();
+        Ok(Self {
+            assassin,
+            join_handle: thread::spawn(move || {
+                capture_task(victim, writer, capture_receiver, status)
+            }),
+        })
+    }
+
+    pub fn join(self) -> BoxResult<()> {
+        self.assassin.kill_victim();
+        self.join_handle.join()?
+    }
+}
+
+fn capture_task(
+    victim: SyncFlagVictim,
+    mut writer: BufWriter<File>,
+    capture_receiver: Receiver<CaptureRecord>,
+    status: ConsoleOutput,
+) -> BoxResult<()> {
+    while victim.is_alive() {
+        match capture_receiver.recv_timeout(CAPTURE_FLUSH_PERIOD) {
+            Ok(record) => {
+                if let Err(e) = record.write_to(&mut writer) {
+                    status.print(format!("> [capture_task] write error {:#?}", e));
+                    return Err(Box::new(e));
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => writer.flush().box_err()?,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    // whatever was already queued still belongs in the capture
+    for record in capture_receiver.try_iter() {
+        record.write_to(&mut writer).box_err()?;
+    }
+    writer.flush().box_err()
+}
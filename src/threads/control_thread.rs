@@ -0,0 +1,346 @@
+use crate::{
+    config::{DisplayMode, LineEnding, ProcessorInfo},
+    threads::user_console_thread::send_line,
+    utils::{
+        console_output::ConsoleOutput,
+        line_printer::LinePrinter,
+        line_taps::LineTaps,
+        ring_buf_queue::RingBufQProducer,
+        sync_flag::SyncFlagAssassin,
+        user_io::{BoxResult, RaisedError},
+        waker::Waker,
+    },
+};
+#[cfg(unix)]
+use crate::{
+    threads::serial_console_thread::{IDLE_POLL_PERIOD, WRITE_RETRY_PERIOD},
+    utils::{
+        socket_buffer::{SocketBuffer, MAX_CLIENT_BACKLOG},
+        sync_flag::{new_sync_flag, SyncFlagVictim},
+        user_io::{box_err_msg, BoxErr},
+        waker::{new_waker, wait_readable, AsRawFd, WakeReceiver},
+    },
+};
+#[cfg(unix)]
+use std::{
+    fs::remove_file,
+    io::ErrorKind,
+    os::unix::net::{UnixListener, UnixStream},
+    sync::mpsc::Receiver,
+    thread,
+};
+use std::{path::Path, thread::JoinHandle};
+
+/// File name of the control socket in the log dir.
+pub const CONTROL_SOCKET_NAME: &str = "jfconsole.sock";
+
+/// A processor scripts can send to, with its own queue to the port and a
+/// printer that logs what was sent.
+pub struct ControlTarget {
+    pub processor_name: String,
+    pub port_name: String,
+    pub tx_line_ending: LineEnding,
+    pub display: DisplayMode,
+    pub write_producer: RingBufQProducer<u8>,
+    pub write_printer: LinePrinter,
+}
+
+impl ControlTarget {
+    pub fn new(
+        processor_info: &ProcessorInfo,
+        write_producer: RingBufQProducer<u8>,
+        write_printer: LinePrinter,
+    ) -> Self {
+        Self {
+            processor_name: processor_info.processor_name.clone(),
+            port_name: processor_info.port_name.clone(),
+            tx_line_ending: processor_info.tx_line_ending,
+            display: processor_info.display,
+            write_producer,
+            write_printer,
+        }
+    }
+
+    /// Sends `line` like the user console would, line ending and all.
+    pub fn send_line(&mut self, line: &str) -> BoxResult<()> {
+        send_line(
+            line,
+            self.display,
+            self.tx_line_ending,
+            &mut self.write_producer,
+            &mut self.write_printer,
+        )
+    }
+}
+
+/// Serves a unix socket that scripts use to drive the session. Commands are
+/// one per line and answered with `ok` or `error <why>`:
+///
+/// - `list` replies `processor <port> <name>` for each processor
+/// - `send <name> <text>` sends a line to a processor
+/// - `subscribe [<name> ...]` streams log lines, of every processor if none
+///   are named, each as `line <log line>`
+/// - `marker <text>` logs a marker line for every processor
+/// - `quit` ends the session, once the console is done with the line it
+///   is reading if stdin is a terminal
+///
+/// Unix domain sockets are needed, so other platforms get an error instead.
+pub struct ControlThread {
+    assassin: SyncFlagAssassin,
+    waker: Waker,
+    join_handle: JoinHandle<BoxResult<()>>,
+}
+
+impl ControlThread {
+    #[cfg(not(unix))]
+    pub fn spawn(
+        _: &Path,
+        _: Vec<ControlTarget>,
+        _: &LineTaps,
+        _: ConsoleOutput,
+        _: &SyncFlagAssassin,
+    ) -> BoxResult<Self> {
+        Err(RaisedError::new("the control socket needs unix"))
+    }
+
+    #[cfg(unix)]
+    pub fn spawn(
+        log_dir: &Path,
+        targets: Vec<ControlTarget>,
+        line_taps: &LineTaps,
+        status: ConsoleOutput,
+        main_thread_assassin: &SyncFlagAssassin,
+    ) -> BoxResult<Self> {
+        let path = log_dir.join(CONTROL_SOCKET_NAME);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(RaisedError::new("another session is using the socket"));
+            }
+            // left behind by a session that did not exit cleanly
+            remove_file(&path).box_err()?;
+        }
+        let listener = UnixListener::bind(&path).box_err()?;
+        listener.set_nonblocking(true).box_err()?;
+        println!("> [control_task] listening on {:?}", path);
+
+        let (waker, wake_receiver) = new_waker().box_err()?;
+        let line_receiver = line_taps.subscribe(Some(waker.clone()));
+        let (victim, assassin) = new_sync_flag();
+        let main_thread_assassin = main_thread_assassin.clone();
+
+        Ok(Self {
+            assassin,
+            waker,
+            join_handle: thread::spawn(move || {
+                let res = control_task(
+                    victim,
+                    &listener,
+                    targets,
+                    line_receiver,
+                    wake_receiver,
+                    status,
+                    main_thread_assassin,
+                );
+                let _ = remove_file(&path);
+                res
+            }),
+        })
+    }
+
+    pub fn join(self) -> BoxResult<()> {
+        self.assassin.kill_victim();
+        self.waker.wake();
+        self.join_handle.join()?
+    }
+}
+
+#[cfg(unix)]
+struct ControlClient {
+    socket: SocketBuffer<UnixStream>,
+    /// Bytes of a command line not ended yet.
+    input: Vec<u8>,
+    /// Indices of the processors whose log lines are streamed.
+    subscription: Vec<usize>,
+}
+
+#[cfg(unix)]
+impl ControlClient {
+    fn new(stream: UnixStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            socket: SocketBuffer::new(stream),
+            input: vec![],
+            subscription: vec![],
+        })
+    }
+
+    fn send(&mut self, line: &str) {
+        self.socket.push(format!("{}\n", line).as_bytes());
+    }
+
+    /// Reads everything available, returning the complete command lines.
+    fn receive(&mut self) -> Vec<String> {
+        self.socket.read_available(&mut self.input);
+        let mut lines = vec![];
+        while let Some(end) = self.input.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            lines.push(line.trim_end_matches('\r').to_string());
+        }
+        if self.input.len() > MAX_CLIENT_BACKLOG {
+            self.socket.closed = true;
+        }
+        lines
+    }
+}
+
+/// Splits the longest processor name off the front of `args`, which must
+/// be followed by a space or nothing. Names may contain spaces.
+#[cfg(unix)]
+fn split_target<'a>(targets: &[ControlTarget], args: &'a str) -> Option<(usize, &'a str)> {
+    targets
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, target)| {
+            let rest = args.strip_prefix(target.processor_name.as_str())?;
+            match rest.is_empty() {
+                true => Some((idx, rest)),
+                false => Some((idx, rest.strip_prefix(' ')?)),
+            }
+        })
+        .max_by_key(|(idx, _)| targets[*idx].processor_name.len())
+}
+
+/// Which processor a log line came from, going by its `<name> r` or
+/// `<name> w` prefix.
+#[cfg(unix)]
+fn line_source(targets: &[ControlTarget], line: &str) -> Option<usize> {
+    targets
+        .iter()
+        .enumerate()
+        .filter(|(_, target)| {
+            line.strip_prefix(target.processor_name.as_str())
+                .is_some_and(|rest| rest.starts_with(" r ") || rest.starts_with(" w "))
+        })
+        .max_by_key(|(_, target)| target.processor_name.len())
+        .map(|(idx, _)| idx)
+}
+
+#[cfg(unix)]
+fn run_command(
+    targets: &mut [ControlTarget],
+    client: &mut ControlClient,
+    command: &str,
+    main_thread_assassin: &SyncFlagAssassin,
+) -> BoxResult<()> {
+    let (verb, args) = command.split_once(' ').unwrap_or((command, ""));
+    match verb {
+        "list" => {
+            for target in targets.iter() {
+                client.send(&format!(
+                    "processor {} {}",
+                    target.port_name, target.processor_name
+                ));
+            }
+        }
+        "send" => {
+            let (idx, line) = split_target(targets, args)
+                .ok_or_else(|| RaisedError::new(&format!("no processor in {:?}", args)))?;
+            targets[idx].send_line(line)?;
+        }
+        "subscribe" => {
+            let mut subscription = vec![];
+            let mut args = args.trim_start();
+            while !args.is_empty() {
+                let (idx, rest) = split_target(targets, args)
+                    .ok_or_else(|| RaisedError::new(&format!("no processor {:?}", args)))?;
+                subscription.push(idx);
+                args = rest.trim_start();
+            }
+            if subscription.is_empty() {
+                subscription = (0..targets.len()).collect();
+            }
+            client.subscription = subscription;
+        }
+        "marker" => {
+            for target in targets.iter_mut() {
+                target.write_printer.push_marker(args);
+            }
+        }
+        "quit" => main_thread_assassin.clone().kill_victim(),
+        _ => return Err(RaisedError::new(&format!("unknown command {:?}", verb))),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn control_task(
+    victim: SyncFlagVictim,
+    listener: &UnixListener,
+    mut targets: Vec<ControlTarget>,
+    line_receiver: Receiver<String>,
+    wake_receiver: WakeReceiver,
+    status: ConsoleOutput,
+    main_thread_assassin: SyncFlagAssassin,
+) -> BoxResult<()> {
+    let mut clients: Vec<ControlClient> = vec![];
+
+    while victim.is_alive() {
+        for line in line_receiver.try_iter() {
+            let Some(source) = line_source(&targets, &line) else {
+                continue;
+            };
+            for client in clients.iter_mut() {
+                if client.subscription.contains(&source) {
+                    client.send(&format!("line {}", line));
+                }
+            }
+        }
+        for client in clients.iter_mut() {
+            client.socket.flush();
+        }
+
+        let mut fds = vec![listener.as_raw_fd(), wake_receiver.as_raw_fd()];
+        fds.extend(clients.iter().map(|client| client.socket.as_raw_fd()));
+        let timeout = match clients.iter().all(|client| client.socket.is_flushed()) {
+            true => IDLE_POLL_PERIOD,
+            false => WRITE_RETRY_PERIOD,
+        };
+        let ready = wait_readable(&fds, timeout).box_err()?;
+        if ready[1] {
+            wake_receiver.drain();
+        }
+
+        for (client, _) in clients.iter_mut().zip(&ready[2..]).filter(|(_, r)| **r) {
+            for command in client.receive() {
+                match run_command(&mut targets, client, &command, &main_thread_assassin) {
+                    Ok(()) => client.send("ok"),
+                    Err(e) => client.send(&format!("error {}", box_err_msg(&e))),
+                }
+            }
+            client.socket.flush();
+        }
+        clients.retain(|client| !client.socket.closed);
+
+        if ready[0] {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => match ControlClient::new(stream) {
+                        Ok(client) => clients.push(client),
+                        Err(e) => {
+                            status.print(format!("> [control_task] client setup failed {:?}", e))
+                        }
+                    },
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        status.print(format!("> [control_task] accept failed {:?}", e));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
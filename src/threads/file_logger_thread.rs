@@ -1,45 +1,62 @@
-use crate::utils::{
-    sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
-    user_io::BoxResult,
+use crate::{
+    config::{Config, LogCompression, LogRotation},
+    utils::{
+        compression::{compress_file, Codec, Encoder},
+        console_output::ConsoleOutput,
+        line_taps::LineTaps,
+        sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
+        user_io::{BoxErr, BoxResult},
+    },
 };
 use chrono::Utc;
 use std::{
-    fs::{create_dir, File, OpenOptions},
-    io::Write,
-    path::Path,
-    sync::mpsc::Receiver,
+    fs::{create_dir_all, remove_file, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, RecvTimeoutError},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
+/// Date part of log and capture file names.
+pub const FILE_NAME_DATE_FMT: &str = "%y%m%d_%H%M%S";
+/// How long the log may go quiet before it is synced to disk. Also bounds
+/// how long `join` waits, since senders outlive the thread.
+pub const LOG_SYNC_PERIOD: Duration = Duration::from_millis(500);
+/// Minimum time between syncs of a compressed log; each one ends a
+/// compressed block, so syncing as often as a plain log would cost most of
+/// the gain.
+pub const COMPRESSED_SYNC_PERIOD: Duration = Duration::from_secs(10);
+
 pub struct FileLoggerThread {
     assassin: SyncFlagAssassin,
     join_handle: JoinHandle<BoxResult<()>>,
 }
 
 impl FileLoggerThread {
+    /// Logs to `cfg.log_dir` with `cfg`'s rotation and compression.
     pub fn spawn(
-        project_name: &str,
+        cfg: &Config,
         line_receiver: Receiver<String>,
+        line_taps: LineTaps,
+        status: ConsoleOutput,
         main_thread_assassin: SyncFlagAssassin,
     ) -> BoxResult<Self> {
-        let path = Path::new(project_name);
-        let _ = create_dir(path);
-
-        let fmt = "%y%m%d_%H%M%S";
-        let now = Utc::now();
-        let file_name = format!("{}_{}.log", project_name, now.format(fmt));
-        let file_path = path.join(Path::new(&file_name));
-        let file = match OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(file_path.clone())
-        {
-            Ok(opened_file) => {
-                println!("> [file_logger_task] opened {:?}", file_path);
-                opened_file
+        let _ = create_dir_all(&cfg.log_dir);
+        let log_file = LogFile::open(
+            &cfg.log_dir,
+            &cfg.project_name,
+            cfg.log_rotation.clone(),
+            cfg.log_compression,
+            status.clone(),
+        );
+        let log_file = match log_file {
+            Ok(log_file) => {
+                status.print(format!("> [file_logger_task] opened {:?}", log_file.path));
+                log_file
             }
             Err(e) => {
-                println!("> [file_logger_task] error {:?}", e);
+                status.print(format!("> [file_logger_task] error {:?}", e));
                 return Err(Box::new(e));
             }
         };
@@ -48,7 +65,13 @@ impl FileLoggerThread {
         Ok(Self {
             assassin,
             join_handle: thread::spawn(move || {
-                file_logger_task(victim, file, line_receiver, main_thread_assassin)
+                file_logger_task(
+                    victim,
+                    log_file,
+                    line_receiver,
+                    line_taps,
+                    main_thread_assassin,
+                )
             }),
         })
     }
@@ -59,32 +82,278 @@ impl FileLoggerThread {
     }
 }
 
+/// A log segment being written, plain or compressed.
+enum LogWriter {
+    Plain(File),
+    Compressed(Encoder<File>),
+}
+
+impl LogWriter {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            LogWriter::Plain(file) => file.write_all(bytes),
+            LogWriter::Compressed(encoder) => encoder.write_all(bytes),
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        match self {
+            LogWriter::Plain(file) => file.sync_all(),
+            LogWriter::Compressed(encoder) => {
+                encoder.flush()?;
+                encoder.get_ref().sync_all()
+            }
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            LogWriter::Plain(file) => file.sync_all(),
+            LogWriter::Compressed(encoder) => encoder.finish()?.sync_all(),
+        }
+    }
+}
+
+/// The session's current log segment. Without rotation it is the only one,
+/// `<project>_<date>.log`, otherwise segments are numbered
+/// `<project>_<date>_000.log`, `<project>_<date>_001.log`, ... Compressed
+/// segments get a `.gz` or `.zst` suffix; size limits count uncompressed
+/// bytes.
+struct LogFile {
+    log_dir: PathBuf,
+    base_name: String,
+    rotation: LogRotation,
+    compression: LogCompression,
+    segment: usize,
+    path: PathBuf,
+    writer: Option<LogWriter>,
+    size: u64,
+    opened_at: Instant,
+    synced_at: Instant,
+    /// Background compression of closed segments, by segment.
+    compressors: Vec<(usize, JoinHandle<()>)>,
+    status: ConsoleOutput,
+}
+
+impl LogFile {
+    fn open(
+        log_dir: &Path,
+        project_name: &str,
+        rotation: LogRotation,
+        compression: LogCompression,
+        status: ConsoleOutput,
+    ) -> io::Result<Self> {
+        let now = Utc::now();
+        let base_name = format!("{}_{}", project_name, now.format(FILE_NAME_DATE_FMT));
+        let mut log_file = Self {
+            log_dir: log_dir.to_path_buf(),
+            base_name,
+            rotation,
+            compression,
+            segment: 0,
+            path: PathBuf::new(),
+            writer: None,
+            size: 0,
+            opened_at: Instant::now(),
+            synced_at: Instant::now(),
+            compressors: vec![],
+            status,
+        };
+        log_file.path = log_file.segment_path(0);
+        log_file.writer = Some(log_file.create_writer()?);
+        Ok(log_file)
+    }
+
+    fn segment_path(&self, segment: usize) -> PathBuf {
+        let file_name = segment_file_name(&self.base_name, &self.rotation, segment);
+        match self.compression.live_codec() {
+            Some(codec) => codec.path(&self.log_dir.join(file_name)),
+            None => self.log_dir.join(file_name),
+        }
+    }
+
+    fn create_writer(&self) -> io::Result<LogWriter> {
+        let file = create_log(&self.path)?;
+        match self.compression.live_codec() {
+            Some(codec) => Ok(LogWriter::Compressed(Encoder::new(codec, file)?)),
+            None => Ok(LogWriter::Plain(file)),
+        }
+    }
+
+    fn writer(&mut self) -> &mut LogWriter {
+        self.writer
+            .as_mut()
+            .expect("log writer is only taken by finish")
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.rotate_if_expired()?;
+        let len = line.len() as u64;
+        let too_big = |max_size| self.size + len > max_size;
+        if self.size > 0 && self.rotation.max_size.is_some_and(too_big) {
+            self.rotate()?;
+        }
+        self.writer().write_all(line.as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Moves on to the next segment once the rotation interval has passed,
+    /// unless nothing was logged since the last rotation.
+    fn rotate_if_expired(&mut self) -> io::Result<()> {
+        let too_old = |interval| self.opened_at.elapsed() >= interval;
+        if self.size > 0 && self.rotation.interval.is_some_and(too_old) {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs to disk, returning false if a compressed log synced too
+    /// recently.
+    fn sync(&mut self) -> io::Result<bool> {
+        let compressed = self.compression.live_codec().is_some();
+        if compressed && self.synced_at.elapsed() < COMPRESSED_SYNC_PERIOD {
+            return Ok(false);
+        }
+        self.writer().sync()?;
+        self.synced_at = Instant::now();
+        Ok(true)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let closed_path = self.path.clone();
+        self.writer.take().map_or(Ok(()), LogWriter::finish)?;
+        self.segment += 1;
+        self.path = self.segment_path(self.segment);
+        self.writer = Some(self.create_writer()?);
+        self.size = 0;
+        self.opened_at = Instant::now();
+        let msg = format!("> [file_logger_task] rotated to {:?}", self.path);
+        self.status.print(msg);
+
+        let expired = self
+            .rotation
+            .keep
+            .and_then(|keep| self.segment.checked_sub(keep));
+        // a segment about to be deleted is not worth compressing
+        if self.compression == LogCompression::Rotated && expired != Some(self.segment - 1) {
+            let status = self.status.clone();
+            let handle = thread::spawn(move || compress_segment(&closed_path, &status));
+            self.compressors.push((self.segment - 1, handle));
+        }
+        if let Some(expired) = expired {
+            self.join_compressors(expired);
+            self.remove_segment(expired);
+        }
+        Ok(())
+    }
+
+    /// Waits for the compression of segments up to `last_segment`.
+    fn join_compressors(&mut self, last_segment: usize) {
+        let (done, pending) = std::mem::take(&mut self.compressors)
+            .into_iter()
+            .partition(|(segment, _)| *segment <= last_segment);
+        self.compressors = pending;
+        for (_, handle) in done {
+            let _ = handle.join();
+        }
+    }
+
+    fn remove_segment(&self, segment: usize) {
+        let path = self.segment_path(segment);
+        let mut removed = remove_file(&path);
+        if self.compression == LogCompression::Rotated && removed.is_err() {
+            removed = remove_file(Codec::Gzip.path(&path));
+        }
+        if let Err(e) = removed {
+            let msg = format!("> [file_logger_task] removing {:?} failed {:?}", path, e);
+            self.status.print(msg);
+        }
+    }
+
+    /// Finishes the open segment and any background compression.
+    fn finish(mut self) -> io::Result<()> {
+        self.join_compressors(usize::MAX);
+        self.writer.take().map_or(Ok(()), LogWriter::finish)
+    }
+}
+
+fn segment_file_name(base_name: &str, rotation: &LogRotation, segment: usize) -> String {
+    match rotation.is_enabled() {
+        true => format!("{}_{:03}.log", base_name, segment),
+        false => format!("{}.log", base_name),
+    }
+}
+
+/// Replaces a closed segment with its gzipped copy.
+fn compress_segment(path: &Path, status: &ConsoleOutput) {
+    let gz_path = Codec::Gzip.path(path);
+    match compress_file(path, &gz_path, Codec::Gzip) {
+        Ok(()) => {
+            let _ = remove_file(path);
+        }
+        Err(e) => {
+            let msg = format!("> [file_logger_task] compressing {:?} failed {:?}", path, e);
+            status.print(msg);
+            let _ = remove_file(&gz_path);
+        }
+    }
+}
+
+fn create_log(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(path)
+}
+
 fn file_logger_task(
     victim: SyncFlagVictim,
-    mut file: File,
+    mut log_file: LogFile,
     line_receiver: Receiver<String>,
+    line_taps: LineTaps,
     main_thread_assassin: SyncFlagAssassin,
 ) -> BoxResult<()> {
     let mut synced = true;
     while victim.is_alive() {
-        // let duration = Duration::from_millis(1000);
-        if let Ok(mut line) = line_receiver.recv() {
-            line.push('\n');
-            if let Err(e) = file.write_all(line.as_bytes()) {
-                println!("> [file_logger_task] write error {:#?}", e);
-                main_thread_assassin.kill_victim();
-                return Err(Box::new(e));
-            } else {
-                synced = false;
+        match line_receiver.recv_timeout(LOG_SYNC_PERIOD) {
+            Ok(mut line) => {
+                line_taps.send(&line);
+                line.push('\n');
+                if let Err(e) = log_file.write_line(&line) {
+                    let msg = format!("> [file_logger_task] write error {:#?}", e);
+                    log_file.status.print(msg);
+                    main_thread_assassin.kill_victim();
+                    return Err(Box::new(e));
+                } else {
+                    synced = false;
+                }
             }
-        } else if synced {
-        } else if let Err(e) = file.sync_all() {
-            println!("> [file_logger_task] sync error {:#?}", e);
-            main_thread_assassin.kill_victim();
-            return Err(Box::new(e));
-        } else {
-            synced = true;
+            Err(RecvTimeoutError::Timeout) => {
+                // a quiet log still starts its next segment on time
+                let res = log_file.rotate_if_expired().and_then(|()| match synced {
+                    true => Ok(true),
+                    false => log_file.sync(),
+                });
+                match res {
+                    Ok(done) => synced = done,
+                    Err(e) => {
+                        let msg = format!("> [file_logger_task] sync error {:#?}", e);
+                        log_file.status.print(msg);
+                        main_thread_assassin.kill_victim();
+                        return Err(Box::new(e));
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
         }
     }
-    Ok(())
+    // lines queued before the join still belong in the log
+    for mut line in line_receiver.try_iter() {
+        line_taps.send(&line);
+        line.push('\n');
+        log_file.write_line(&line).box_err()?;
+    }
+    log_file.finish().box_err()
 }
@@ -0,0 +1,480 @@
+use crate::{
+    threads::{
+        control_thread::ControlTarget,
+        serial_console_thread::{IDLE_POLL_PERIOD, WRITE_RETRY_PERIOD},
+    },
+    utils::{
+        console_output::ConsoleOutput,
+        line_printer::parse_log_line,
+        line_taps::LineTaps,
+        socket_buffer::SocketBuffer,
+        sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
+        user_io::{box_err_msg, BoxErr, BoxResult},
+        waker::{new_waker, wait_readable, AsRawFd, WakeReceiver, Waker},
+        websocket::{
+            accept_key, decode_frame, encode_frame, OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG,
+            OPCODE_TEXT,
+        },
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::mpsc::Receiver,
+    thread::{self, JoinHandle},
+};
+
+/// The browser viewer served at `/`.
+const DASHBOARD_HTML: &str = include_str!("http_dashboard.html");
+/// Requests, body included, larger than this are refused.
+const MAX_REQUEST_LEN: usize = 0x10000;
+
+/// Serves the session on localhost: the dashboard at `/`, the processors as
+/// JSON at `/processors`, every log line on the WebSocket at `/ws`, and
+/// `POST /send` with `{"processor": <name>, "line": <text>}` to send a line.
+pub struct HttpThread {
+    assassin: SyncFlagAssassin,
+    waker: Waker,
+    join_handle: JoinHandle<BoxResult<()>>,
+}
+
+impl HttpThread {
+    pub fn spawn(
+        tcp_port: u16,
+        targets: Vec<ControlTarget>,
+        line_taps: &LineTaps,
+        status: ConsoleOutput,
+    ) -> BoxResult<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, tcp_port)).box_err()?;
+        listener.set_nonblocking(true).box_err()?;
+        println!(
+            "> [http_task] dashboard on http://{}/",
+            listener.local_addr().box_err()?
+        );
+
+        let (waker, wake_receiver) = new_waker().box_err()?;
+        let line_receiver = line_taps.subscribe(Some(waker.clone()));
+        let (victim, assassin) = new_sync_flag();
+
+        Ok(Self {
+            assassin,
+            waker,
+            join_handle: thread::spawn(move || {
+                http_task(
+                    victim,
+                    listener,
+                    targets,
+                    line_receiver,
+                    wake_receiver,
+                    status,
+                )
+            }),
+        })
+    }
+
+    pub fn join(self) -> BoxResult<()> {
+        self.assassin.kill_victim();
+        self.waker.wake();
+        self.join_handle.join()?
+    }
+}
+
+#[derive(Serialize)]
+struct ProcessorDto<'a> {
+    name: &'a str,
+    port: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SendDto {
+    processor: String,
+    line: String,
+}
+
+/// One log line on the WebSocket. `dir` is `r` or `w`, and `complete` is
+/// `|` for a whole line, ` ` for a wrapped one and `#` for a marker.
+#[derive(Serialize)]
+struct LineDto<'a> {
+    processor: &'a str,
+    dir: &'a str,
+    ts_ms: i64,
+    complete: char,
+    text: &'a str,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the request comes from a page on this machine's own origin.
+    /// Checking the host keeps DNS rebinding out, and checking the origin
+    /// keeps other sites' pages from driving the bench.
+    fn is_local(&self) -> bool {
+        let Some(host) = self.header("host") else {
+            return false;
+        };
+        let hostname = match host.rsplit_once(':') {
+            Some((hostname, port)) if !port.contains(']') => hostname,
+            _ => host,
+        };
+        matches!(hostname, "localhost" | "127.0.0.1" | "[::1]")
+            && self
+                .header("origin")
+                .is_none_or(|origin| origin == format!("http://{}", host))
+    }
+}
+
+/// Takes the first complete request off `input`. Returns `None` while it is
+/// still arriving.
+fn parse_request(input: &mut Vec<u8>) -> Option<Request> {
+    let head_len = input.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+    let head = String::from_utf8_lossy(&input[..head_len]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let headers: Vec<_> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: vec![],
+    };
+    let body_len: usize = request
+        .header("content-length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    // a bogus length waits for more input until the request is too large
+    let request_len = head_len.saturating_add(body_len);
+    if input.len() < request_len {
+        return None;
+    }
+    request.body = input[head_len..request_len].to_vec();
+    input.drain(..request_len);
+    Some(request)
+}
+
+fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut out = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+        Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    out.extend_from_slice(body);
+    out
+}
+
+fn json_response(status: &str, value: &impl Serialize) -> Vec<u8> {
+    let body = serde_json::to_string(value).unwrap_or_default();
+    response(status, "application/json", body.as_bytes())
+}
+
+fn error_response(status: &str, msg: &str) -> Vec<u8> {
+    json_response(status, &json!({ "error": msg }))
+}
+
+struct HttpClient {
+    socket: SocketBuffer<TcpStream>,
+    input: Vec<u8>,
+    /// Upgraded to a WebSocket, which gets every log line.
+    websocket: bool,
+    /// Close once the backlog is sent.
+    closing: bool,
+}
+
+impl HttpClient {
+    fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            socket: SocketBuffer::new(stream),
+            input: vec![],
+            websocket: false,
+            closing: false,
+        })
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        self.socket.push(bytes);
+    }
+
+    fn flush(&mut self) {
+        self.socket.flush();
+        if self.closing && self.socket.is_flushed() {
+            self.socket.closed = true;
+        }
+    }
+
+    fn receive(&mut self) {
+        self.socket.read_available(&mut self.input);
+    }
+
+    /// Answers pings and closes; anything else a browser sends is ignored.
+    fn handle_frames(&mut self) {
+        while let Some(frame) = decode_frame(&mut self.input) {
+            match frame.opcode {
+                OPCODE_PING => self.send(&encode_frame(OPCODE_PONG, &frame.payload)),
+                OPCODE_CLOSE => {
+                    self.send(&encode_frame(OPCODE_CLOSE, &frame.payload));
+                    self.closing = true;
+                }
+                _ => {}
+            }
+        }
+        if self.input.len() > MAX_REQUEST_LEN {
+            self.socket.closed = true;
+        }
+    }
+
+    fn handle_request(&mut self, targets: &mut [ControlTarget]) {
+        let Some(request) = parse_request(&mut self.input) else {
+            if self.input.len() > MAX_REQUEST_LEN {
+                self.send(&error_response(
+                    "413 Payload Too Large",
+                    "request too large",
+                ));
+                self.closing = true;
+            }
+            return;
+        };
+        self.closing = true;
+        if !request.is_local() {
+            self.send(&error_response("403 Forbidden", "not a local origin"));
+            return;
+        }
+        let reply = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/" | "/index.html") => response(
+                "200 OK",
+                "text/html; charset=utf-8",
+                DASHBOARD_HTML.as_bytes(),
+            ),
+            ("GET", "/processors") => {
+                let processors: Vec<_> = targets
+                    .iter()
+                    .map(|target| ProcessorDto {
+                        name: &target.processor_name,
+                        port: &target.port_name,
+                    })
+                    .collect();
+                json_response("200 OK", &processors)
+            }
+            ("GET", "/ws") => match request.header("sec-websocket-key") {
+                Some(key)
+                    if request
+                        .header("upgrade")
+                        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) =>
+                {
+                    self.closing = false;
+                    self.websocket = true;
+                    format!(
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                        Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                        accept_key(key)
+                    )
+                    .into_bytes()
+                }
+                _ => error_response("400 Bad Request", "expected a websocket upgrade"),
+            },
+            // a JSON body can not be posted cross-origin without a preflight
+            ("POST", "/send")
+                if !request
+                    .header("content-type")
+                    .is_some_and(|content_type| content_type.starts_with("application/json")) =>
+            {
+                error_response("415 Unsupported Media Type", "expected application/json")
+            }
+            ("POST", "/send") => match serde_json::from_slice::<SendDto>(&request.body) {
+                Ok(send) => match targets
+                    .iter_mut()
+                    .find(|target| target.processor_name == send.processor)
+                {
+                    Some(target) => match target.send_line(&send.line) {
+                        Ok(()) => json_response("200 OK", &json!({ "ok": true })),
+                        Err(e) => error_response("400 Bad Request", &box_err_msg(&e)),
+                    },
+                    None => error_response("404 Not Found", "no such processor"),
+                },
+                Err(e) => error_response("400 Bad Request", &e.to_string()),
+            },
+            _ => error_response("404 Not Found", "no such endpoint"),
+        };
+        self.send(&reply);
+    }
+}
+
+/// The WebSocket frame for a log line, if it is one of a processor's.
+fn line_frame(line: &str) -> Option<Vec<u8>> {
+    let log_line = parse_log_line(line)?;
+    let (processor, dir) = log_line.prefix.rsplit_once(' ')?;
+    let line_dto = LineDto {
+        processor,
+        dir,
+        ts_ms: log_line.timestamp_ns / 1_000_000,
+        complete: log_line.complete,
+        text: log_line.text,
+    };
+    let json = serde_json::to_string(&line_dto).ok()?;
+    Some(encode_frame(OPCODE_TEXT, json.as_bytes()))
+}
+
+fn http_task(
+    victim: SyncFlagVictim,
+    listener: TcpListener,
+    mut targets: Vec<ControlTarget>,
+    line_receiver: Receiver<String>,
+    wake_receiver: WakeReceiver,
+    status: ConsoleOutput,
+) -> BoxResult<()> {
+    let mut clients: Vec<HttpClient> = vec![];
+
+    while victim.is_alive() {
+        for line in line_receiver.try_iter() {
+            if !clients.iter().any(|client| client.websocket) {
+                continue;
+            }
+            let Some(frame) = line_frame(&line) else {
+                continue;
+            };
+            for client in clients.iter_mut().filter(|client| client.websocket) {
+                client.send(&frame);
+            }
+        }
+        for client in clients.iter_mut() {
+            client.flush();
+        }
+        clients.retain(|client| !client.socket.closed);
+
+        let mut fds = vec![listener.as_raw_fd(), wake_receiver.as_raw_fd()];
+        fds.extend(clients.iter().map(|client| client.socket.as_raw_fd()));
+        let timeout = match clients.iter().all(|client| client.socket.is_flushed()) {
+            true => IDLE_POLL_PERIOD,
+            false => WRITE_RETRY_PERIOD,
+        };
+        let ready = wait_readable(&fds, timeout).box_err()?;
+        if ready[1] {
+            wake_receiver.drain();
+        }
+
+        for (client, _) in clients.iter_mut().zip(&ready[2..]).filter(|(_, r)| **r) {
+            client.receive();
+            match client.websocket {
+                true => client.handle_frames(),
+                false if !client.closing => client.handle_request(&mut targets),
+                // the rest of a request already answered
+                false => client.input.clear(),
+            }
+            client.flush();
+        }
+        clients.retain(|client| !client.socket.closed);
+
+        if ready[0] {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => match HttpClient::new(stream) {
+                        Ok(client) => clients.push(client),
+                        Err(e) => {
+                            status.print(format!("> [http_task] client setup failed {:?}", e))
+                        }
+                    },
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        status.print(format!("> [http_task] accept failed {:?}", e));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_request_and_keeps_the_next_one() {
+        let mut input = b"POST /send?x=1 HTTP/1.1\r\nHost: localhost\r\n\
+            Content-Length: 5\r\n\r\nhelloGET / HTTP/1.1\r\n"
+            .to_vec();
+        let request = parse_request(&mut input).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/send");
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.body, b"hello");
+        assert_eq!(input, b"GET / HTTP/1.1\r\n");
+        assert!(parse_request(&mut input).is_none());
+    }
+
+    #[test]
+    fn waits_for_a_whole_request() {
+        let mut input = b"GET / HTTP/1.1\r\nHost: localhost\r\n".to_vec();
+        assert!(parse_request(&mut input).is_none());
+        let partial = b"POST /send HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello";
+        let mut input = partial.to_vec();
+        assert!(parse_request(&mut input).is_none());
+        assert_eq!(input, partial);
+    }
+
+    #[test]
+    fn parses_malformed_requests_without_panicking() {
+        let mut input = b"\r\n\r\n".to_vec();
+        let request = parse_request(&mut input).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("", ""));
+        assert!(input.is_empty());
+
+        // header lines without a colon are skipped, bad lengths mean no body
+        let mut input = b"GET\r\nno colon\r\nContent-Length: -3\r\n\r\n".to_vec();
+        let request = parse_request(&mut input).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.headers.len(), 1);
+        assert!(request.body.is_empty());
+
+        let mut input = b"\xff\xfe /\xff HTTP/1.1\r\n\r\n".to_vec();
+        let request = parse_request(&mut input).unwrap();
+        assert_eq!(request.path, "/\u{fffd}");
+
+        let mut input = b"POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n".to_vec();
+        assert!(parse_request(&mut input).is_none());
+    }
+
+    #[test]
+    fn only_local_origins_are_local() {
+        let request = |headers: &[(&str, &str)]| Request {
+            method: "GET".into(),
+            path: "/".into(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            body: vec![],
+        };
+        assert!(request(&[("Host", "localhost:8080")]).is_local());
+        assert!(request(&[("Host", "[::1]:80"), ("Origin", "http://[::1]:80")]).is_local());
+        assert!(!request(&[]).is_local());
+        assert!(!request(&[("Host", "evil.example:8080")]).is_local());
+        assert!(!request(&[("Host", "127.0.0.1"), ("Origin", "http://evil.example")]).is_local());
+    }
+}
@@ -1,19 +1,36 @@
 use crate::{
-    config::{read_config::UserSelectFileRes, ProcessorInfo, Config},
+    config::{
+        cli_args::{CliArgs, USAGE},
+        read_config::UserSelectFileRes,
+        Config, ProcessorInfo,
+    },
     threads::{
+        bridge_thread::{BridgeThread, DEFAULT_BRIDGE_ADDR},
+        capture_thread::CaptureThread,
+        control_thread::{ControlTarget, ControlThread},
         file_logger_thread::FileLoggerThread,
-        serial_console_thread::SerialConsoleThread,
+        http_thread::HttpThread,
+        replay_thread::replay_task,
+        serial_console_thread::{PortTaps, SerialConsoleThread},
+        tui_console_thread::tui_console_task,
         user_console_thread::{user_console_task, ProcessorUserConsoleWriter},
     },
     utils::{
-        line_printer::LinePrinter,
+        capture::{CaptureSender, MAX_CAPTURE_PROCESSORS},
+        console_output::{ConsoleOutput, ConsoleStyle},
+        line_printer::{LinePrinter, WriteRoute, WriteRoutes},
+        line_taps::LineTaps,
         ring_buf_queue::new_ring_buf_q,
         sync_flag::new_sync_flag,
-        user_io::{BoxErr, BoxResult},
+        user_io::{box_err_msg, BoxErr, BoxResult},
+        waker::new_waker,
     },
 };
-use serialport::{available_ports, SerialPortType};
-use std::{path::Path, sync::mpsc::channel};
+use serialport::available_ports;
+use std::{
+    io::IsTerminal,
+    sync::{mpsc::channel, Arc},
+};
 use thread_priority::{set_current_thread_priority, ThreadPriority};
 
 pub const BUFFER_SIZE: usize = 0x1000;
@@ -35,34 +52,51 @@ impl ProcessorInfo {
     pub fn available_processors() -> BoxResult<Vec<ProcessorInfo>> {
         let ports = available_ports().box_err()?;
 
-        let mut procs = vec![];
-        for serial_port_info in ports {
-            if let SerialPortType::UsbPort(usb_port_info) = serial_port_info.port_type {
-                procs.push(ProcessorInfo::new(
-                    serial_port_info.port_name,
-                    usb_port_info,
-                ));
-            }
-        }
+        let procs = ports
+            .into_iter()
+            .map(|port| ProcessorInfo::new(port.port_name, port.port_type))
+            .collect();
         Ok(procs)
     }
 }
 
-pub fn main_task() {
+pub fn main_task(cli_args: CliArgs) {
+    if cli_args.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Some(replay_path) = &cli_args.replay_path {
+        replay_task(&cli_args, replay_path);
+        return;
+    }
     println!("Welcome!\n\n");
 
     let proc_v = ProcessorInfo::available_processors().unwrap();
-    if proc_v.is_empty() {
-        println!("> [main_task] No com ports found");
-        return;
-    }
-    let cfg = loop {
-        match Config::user_select_file(&proc_v) {
-            UserSelectFileRes::Select(cfg) => break cfg,
-            UserSelectFileRes::NoConfigs => break Config::user_create_custom(proc_v),
-            UserSelectFileRes::SelectCustom => break Config::user_create_custom(proc_v),
-            UserSelectFileRes::InvalidEntry => continue,
+    let cfg = if cli_args.is_scripted() {
+        match cli_args.scripted_config(proc_v) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                println!("> [main_task] {}", box_err_msg(&e));
+                return;
+            }
+        }
+    } else {
+        if proc_v.is_empty() {
+            println!("> [main_task] No com ports found, enter a device path instead");
         }
+        let mut cfg = loop {
+            match Config::user_select_file(&proc_v) {
+                UserSelectFileRes::Select(cfg) => break cfg,
+                UserSelectFileRes::NoConfigs => break Config::user_create_custom(proc_v),
+                UserSelectFileRes::SelectCustom => break Config::user_create_custom(proc_v),
+                UserSelectFileRes::InvalidEntry => continue,
+            }
+        };
+        if let Err(e) = cli_args.apply_overrides(&mut cfg) {
+            println!("> [main_task] {}", box_err_msg(&e));
+            return;
+        }
+        cfg
     };
     if cfg.processors.is_empty() {
         println!(
@@ -71,53 +105,268 @@ pub fn main_task() {
         );
         return;
     }
+    if cli_args.capture && cfg.processors.len() > MAX_CAPTURE_PROCESSORS {
+        println!(
+            "> [main_task] --capture records at most {} processors, {:?} has {}",
+            MAX_CAPTURE_PROCESSORS,
+            cfg.project_path,
+            cfg.processors.len()
+        );
+        return;
+    }
 
     let (main_thread_victim, main_thread_assassin) = new_sync_flag();
 
+    let tui = cli_args.tui
+        && cfg!(unix)
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal();
+    if cli_args.tui && !tui {
+        println!("> [main_task] --tui needs a unix terminal, using the plain console");
+    }
+    let (pane_sender, pane_receiver) = channel();
+    let console_output = |pane| match tui {
+        true => ConsoleOutput::Pane {
+            pane,
+            sender: pane_sender.clone(),
+        },
+        false => ConsoleOutput::Stdout,
+    };
+    let status_output = match tui {
+        true => ConsoleOutput::Status(pane_sender.clone()),
+        false => ConsoleOutput::Stdout,
+    };
+
     let (line_sender, line_receiver) = channel();
-    let file_logger_thread =
-        FileLoggerThread::spawn(&cfg.project_name, line_receiver, main_thread_assassin).unwrap();
+    let line_taps = LineTaps::default();
+    let file_logger_thread = FileLoggerThread::spawn(
+        &cfg,
+        line_receiver,
+        line_taps.clone(),
+        status_output.clone(),
+        main_thread_assassin.clone(),
+    )
+    .unwrap();
+
+    let highlights: Arc<[_]> = cfg.highlights.into();
+    let console_style = |processor_info: &ProcessorInfo| ConsoleStyle {
+        color: processor_info.color,
+        highlights: highlights.clone(),
+        ansi: processor_info.ansi,
+    };
+
+    let processors = cfg.processors.into_vec();
+    let (capture_sender, capture_receiver) = channel();
+    let capture_thread = match cli_args.capture {
+        true => {
+            let names: Vec<_> = processors
+                .iter()
+                .map(|p| p.processor_name.clone())
+                .collect();
+            let capture_thread = CaptureThread::spawn(
+                &cfg.log_dir,
+                &cfg.project_name,
+                &names,
+                capture_receiver,
+                status_output.clone(),
+            );
+            Some(capture_thread.unwrap())
+        }
+        false => None,
+    };
+    let capture = |pane: usize| {
+        // fits, configs with more processors than a capture can index are refused
+        capture_thread.as_ref().map(|_| CaptureSender {
+            processor: pane as u8,
+            sender: capture_sender.clone(),
+        })
+    };
+
+    let mut wakers_v = vec![];
+    for _ in processors.iter() {
+        wakers_v.push(new_waker().unwrap());
+    }
+    let mut write_routes_v: Vec<_> = processors.iter().map(|_| WriteRoutes::new()).collect();
+    let mut write_consumers_v: Vec<_> = processors.iter().map(|_| vec![]).collect();
+    for (source_idx, processor_info) in processors.iter().enumerate() {
+        for trigger in processor_info.triggers.iter() {
+            let write_routes = &mut write_routes_v[source_idx];
+            if write_routes.contains_key(&trigger.target) {
+                continue;
+            }
+            let target_name = |p: &ProcessorInfo| p.processor_name == trigger.target;
+            if let Some(target_idx) = processors.iter().position(target_name) {
+                let (mut write_producer, write_consumer) = new_ring_buf_q();
+                write_producer.set_waker(wakers_v[target_idx].0.clone());
+                let tx_line_ending = processors[target_idx].tx_line_ending;
+                let route = WriteRoute {
+                    write_producer,
+                    tx_line_ending,
+                };
+                write_routes.insert(trigger.target.clone(), route);
+                write_consumers_v[target_idx].push(write_consumer);
+            }
+        }
+    }
 
+    let bridge_addr = cli_args.bridge_bind.unwrap_or(DEFAULT_BRIDGE_ADDR);
     let mut writer_v = vec![];
     let mut serial_console_thread_v = vec![];
-    for processor_info in cfg.processors.into_vec() {
-        let (write_producer, write_consumer) = new_ring_buf_q();
-        let mut write_consumers = vec![write_consumer];
-
-        let mut line_write_producer = None;
-        if processor_info.processor_name == "f4" {
-            let (write_producer, write_consumer) = new_ring_buf_q();
-            line_write_producer = Some(write_producer);
-            write_consumers.push(write_consumer);
-        }
-        serial_console_thread_v.push(SerialConsoleThread::<BUFFER_SIZE>::spawn(
-            LinePrinter::new(
-                format!("{} r", processor_info.processor_name),
-                LINE_WIDTH,
-                line_sender.clone(),
-                line_write_producer,
-            ),
-            &processor_info,
-            write_consumers,
-        ).unwrap());
-        writer_v.push(ProcessorUserConsoleWriter::new(
-            Path::new(&cfg.project_name),
-            &processor_info,
-            LinePrinter::new(
+    let mut bridge_thread_v = vec![];
+    let mut control_targets = vec![];
+    let mut http_targets = vec![];
+    let routed = write_routes_v
+        .into_iter()
+        .zip(write_consumers_v)
+        .zip(wakers_v);
+    for (pane, (processor_info, ((write_routes, mut write_consumers), wakers))) in
+        processors.iter().zip(routed).enumerate()
+    {
+        let (mut write_producer, write_consumer) = new_ring_buf_q();
+        write_producer.set_waker(wakers.0.clone());
+        write_consumers.insert(0, write_consumer);
+
+        let mut read_printer = LinePrinter::new(
+            format!("{} r", processor_info.processor_name),
+            LINE_WIDTH,
+            line_sender.clone(),
+            console_output(pane),
+            console_style(processor_info),
+            write_routes,
+            processor_info.triggers.clone(),
+        );
+        read_printer.set_display(processor_info.display);
+        // the console, a bridge and each script queue echo what they send
+        let write_printer = || {
+            let mut write_printer = LinePrinter::new(
                 format!("{} w", processor_info.processor_name),
                 LINE_WIDTH,
                 line_sender.clone(),
-                None,
-            ),
+                console_output(pane),
+                console_style(processor_info),
+                WriteRoutes::new(),
+                Box::new([]),
+            );
+            write_printer.set_display(processor_info.display);
+            write_printer
+        };
+
+        let mut rx_fanout = None;
+        if let Some(bridge) = processor_info.bridge {
+            let (mut rx_producer, rx_consumer) = new_ring_buf_q();
+            let bridge_wakers = new_waker().unwrap();
+            rx_producer.set_waker(bridge_wakers.0.clone());
+            let (mut remote_producer, remote_consumer) = new_ring_buf_q();
+            remote_producer.set_waker(wakers.0.clone());
+            match BridgeThread::<BUFFER_SIZE>::spawn(
+                processor_info,
+                bridge,
+                bridge_addr,
+                rx_consumer,
+                bridge_wakers,
+                (remote_producer, write_printer()),
+                status_output.clone(),
+            ) {
+                Ok(bridge_thread) => {
+                    write_consumers.push(remote_consumer);
+                    rx_fanout = Some(rx_producer);
+                    bridge_thread_v.push(bridge_thread);
+                }
+                Err(e) => println!(
+                    "> [main_task] {} bridge on port {} failed: {}",
+                    processor_info.processor_name,
+                    bridge.tcp_port,
+                    box_err_msg(&e)
+                ),
+            }
+        }
+
+        // scripts and the dashboard each get their own queue to the port
+        let mut control_target = || {
+            let (mut control_producer, control_consumer) = new_ring_buf_q();
+            control_producer.set_waker(wakers.0.clone());
+            write_consumers.push(control_consumer);
+            ControlTarget::new(processor_info, control_producer, write_printer())
+        };
+        if cli_args.control {
+            control_targets.push(control_target());
+        }
+        if cli_args.http_port.is_some() {
+            http_targets.push(control_target());
+        }
+
+        serial_console_thread_v.push(
+            SerialConsoleThread::<BUFFER_SIZE>::spawn(
+                read_printer,
+                processor_info,
+                write_consumers,
+                wakers,
+                PortTaps {
+                    capture: capture(pane),
+                    rx_fanout,
+                },
+            )
+            .unwrap(),
+        );
+        writer_v.push(ProcessorUserConsoleWriter::new(
+            &cfg.log_dir,
+            processor_info,
+            write_printer(),
             write_producer,
         ));
     }
-    user_console_task(main_thread_victim, &mut writer_v);
+    let control_thread = match cli_args.control {
+        true => match ControlThread::spawn(
+            &cfg.log_dir,
+            control_targets,
+            &line_taps,
+            status_output.clone(),
+            &main_thread_assassin,
+        ) {
+            Ok(control_thread) => Some(control_thread),
+            Err(e) => {
+                println!("> [main_task] control socket failed: {}", box_err_msg(&e));
+                None
+            }
+        },
+        false => None,
+    };
+    let http_thread = match cli_args.http_port {
+        Some(tcp_port) => {
+            match HttpThread::spawn(tcp_port, http_targets, &line_taps, status_output.clone()) {
+                Ok(http_thread) => Some(http_thread),
+                Err(e) => {
+                    println!("> [main_task] http server failed: {}", box_err_msg(&e));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    if tui {
+        if let Err(e) = tui_console_task(main_thread_victim, &mut writer_v, pane_receiver) {
+            println!("> [main_task] tui error {:?}", e);
+        }
+    } else {
+        user_console_task(main_thread_victim, &mut writer_v);
+    }
 
     for serial_console_thread in serial_console_thread_v {
         let _ = serial_console_thread.join();
     }
+    for bridge_thread in bridge_thread_v {
+        let _ = bridge_thread.join();
+    }
+    if let Some(control_thread) = control_thread {
+        let _ = control_thread.join();
+    }
+    if let Some(http_thread) = http_thread {
+        let _ = http_thread.join();
+    }
     let _ = file_logger_thread.join();
+    if let Some(capture_thread) = capture_thread {
+        let _ = capture_thread.join();
+    }
 
     for writer in writer_v {
         writer.save_history();
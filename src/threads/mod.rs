@@ -1,5 +1,10 @@
-
-pub mod main_thread;
+pub mod bridge_thread;
+pub mod capture_thread;
+pub mod control_thread;
 pub mod file_logger_thread;
+pub mod http_thread;
+pub mod main_thread;
+pub mod replay_thread;
 pub mod serial_console_thread;
+pub mod tui_console_thread;
 pub mod user_console_thread;
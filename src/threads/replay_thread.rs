@@ -0,0 +1,266 @@
+use crate::{
+    config::{cli_args::CliArgs, Config, DisplayMode, ProcessorInfo},
+    threads::main_thread::LINE_WIDTH,
+    utils::{
+        capture::{read_capture_magic, CaptureRecord, RecordKind},
+        compression::{open_maybe_compressed, strip_codec_extension},
+        console_output::{ConsoleOutput, ConsoleStyle},
+        line_printer::{parse_log_line, LinePrinter, WriteRoutes},
+        user_io::{box_err_msg, BoxErr, BoxResult},
+    },
+};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::{mpsc::channel, Arc},
+    thread,
+    time::Duration,
+};
+
+/// Quiet stretches longer than this are cut short, so replaying a soak log
+/// does not sit idle for hours.
+pub const REPLAY_MAX_GAP: Duration = Duration::from_secs(5);
+
+enum ReplayData {
+    Rx(Vec<u8>),
+    Tx(Vec<u8>),
+    Marker(String),
+}
+
+struct ReplayEvent {
+    timestamp_ns: i64,
+    processor: usize,
+    data: ReplayData,
+}
+
+struct Recording {
+    processor_names: Vec<String>,
+    events: Vec<ReplayEvent>,
+    /// Log files hold already formatted lines, so hex dumps stay as text.
+    from_log: bool,
+}
+
+/// Reading stopped before the end of `path`, eg a compressed file whose
+/// session never finished it; what came before still gets replayed.
+fn warn_cut_short(path: &Path, e: &io::Error) {
+    println!(
+        "> [replay_task] {:?} is cut short ({}), replaying what was read",
+        path, e
+    );
+}
+
+fn read_capture(path: &Path) -> BoxResult<Recording> {
+    let mut reader = BufReader::new(open_maybe_compressed(path).box_err()?);
+    read_capture_magic(&mut reader).box_err()?;
+    let mut processor_names = vec![];
+    let mut events = vec![];
+    loop {
+        let record = match CaptureRecord::read_from(&mut reader) {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                warn_cut_short(path, &e);
+                break;
+            }
+        };
+        let processor = record.processor as usize;
+        if processor_names.len() <= processor {
+            processor_names.resize(processor + 1, String::new());
+        }
+        let data = match record.kind {
+            RecordKind::Name => {
+                processor_names[processor] = String::from_utf8_lossy(&record.bytes).into();
+                continue;
+            }
+            RecordKind::Rx => ReplayData::Rx(record.bytes),
+            RecordKind::Tx => ReplayData::Tx(record.bytes),
+        };
+        events.push(ReplayEvent {
+            timestamp_ns: record.timestamp_ns as i64,
+            processor,
+            data,
+        });
+    }
+    Ok(Recording {
+        processor_names,
+        events,
+        from_log: false,
+    })
+}
+
+/// Turns logged lines back into the bytes that produced them. A `|` line
+/// only ends when the next line of its prefix shows it was not wrapped.
+fn read_log(path: &Path) -> BoxResult<Recording> {
+    let reader = BufReader::new(open_maybe_compressed(path).box_err()?);
+    let mut processor_names: Vec<String> = vec![];
+    let mut events: Vec<ReplayEvent> = vec![];
+    let mut open_lines: HashMap<String, usize> = HashMap::new();
+    let end_line = |event: &mut ReplayEvent| {
+        if let ReplayData::Rx(bytes) | ReplayData::Tx(bytes) = &mut event.data {
+            bytes.push(b'\n');
+        }
+    };
+
+    for line in reader.split(b'\n') {
+        let line = match line {
+            Ok(line) => String::from_utf8_lossy(&line).into_owned(),
+            Err(e) => {
+                warn_cut_short(path, &e);
+                break;
+            }
+        };
+        let Some(log_line) = parse_log_line(&line) else {
+            continue;
+        };
+        let Some((name, direction)) = log_line.prefix.rsplit_once(' ') else {
+            continue;
+        };
+        let processor = match processor_names.iter().position(|n| n == name) {
+            Some(processor) => processor,
+            None => {
+                processor_names.push(name.to_string());
+                processor_names.len() - 1
+            }
+        };
+        if log_line.complete == ' ' {
+            if let Some(&idx) = open_lines.get(log_line.prefix) {
+                if let ReplayData::Rx(bytes) | ReplayData::Tx(bytes) = &mut events[idx].data {
+                    bytes.extend_from_slice(log_line.text.as_bytes());
+                }
+                continue;
+            }
+        }
+        if let Some(idx) = open_lines.remove(log_line.prefix) {
+            end_line(&mut events[idx]);
+        }
+        let text = log_line.text.to_string();
+        let data = match (log_line.complete, direction) {
+            ('#', _) => ReplayData::Marker(text),
+            (_, "r") => ReplayData::Rx(text.into_bytes()),
+            (_, "w") => ReplayData::Tx(text.into_bytes()),
+            _ => continue,
+        };
+        if !matches!(data, ReplayData::Marker(_)) {
+            open_lines.insert(log_line.prefix.to_string(), events.len());
+        }
+        events.push(ReplayEvent {
+            timestamp_ns: log_line.timestamp_ns,
+            processor,
+            data,
+        });
+    }
+    for idx in open_lines.into_values() {
+        end_line(&mut events[idx]);
+    }
+    Ok(Recording {
+        processor_names,
+        events,
+        from_log: true,
+    })
+}
+
+/// Waits for Enter; `q` ends the replay.
+fn step_prompt() -> bool {
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => line.trim() != "q",
+    }
+}
+
+/// Plays a `.cap` capture or `.log` file back through `LinePrinter`s without
+/// any hardware, so triggers and highlights can be rerun on old sessions.
+/// Either may be gzip or zstd compressed.
+/// Processors named in `--config` get their settings from it. Triggers only
+/// report what they would have sent.
+pub fn replay_task(cli_args: &CliArgs, replay_path: &Path) {
+    let cfg = match cli_args
+        .config_path
+        .clone()
+        .map(Config::read_config_offline)
+    {
+        Some(Err(e)) => {
+            println!("> [replay_task] {}", box_err_msg(&e));
+            return;
+        }
+        cfg => cfg.and_then(Result::ok),
+    };
+    let file_name = replay_path.to_string_lossy();
+    let recording = match strip_codec_extension(&file_name).ends_with(".cap") {
+        true => read_capture(replay_path),
+        false => read_log(replay_path),
+    };
+    let recording = match recording {
+        Ok(recording) => recording,
+        Err(e) => {
+            println!("> [replay_task] {:?} {}", replay_path, box_err_msg(&e));
+            return;
+        }
+    };
+
+    let (highlights, processors): (Arc<[_]>, Vec<ProcessorInfo>) = match cfg {
+        Some(cfg) => (cfg.highlights.into(), cfg.processors.into_vec()),
+        None => (Arc::new([]), vec![]),
+    };
+    // the log lines go nowhere, the replayed session is already on disk
+    let (line_sender, _) = channel();
+    let mut printers: Vec<_> = recording
+        .processor_names
+        .iter()
+        .map(|name| {
+            let processor_info = processors.iter().find(|p| &p.processor_name == name);
+            let console_style = ConsoleStyle {
+                color: processor_info.and_then(|p| p.color),
+                highlights: highlights.clone(),
+                ansi: processor_info.map(|p| p.ansi).unwrap_or_default(),
+            };
+            let display = match (recording.from_log, processor_info) {
+                (false, Some(processor_info)) => processor_info.display,
+                _ => DisplayMode::Text,
+            };
+            let printer = |direction: &str, triggers| {
+                let mut printer = LinePrinter::new(
+                    format!("{} {}", name, direction),
+                    LINE_WIDTH,
+                    line_sender.clone(),
+                    ConsoleOutput::Stdout,
+                    console_style.clone(),
+                    WriteRoutes::new(),
+                    triggers,
+                );
+                printer.set_display(display);
+                printer
+            };
+            let triggers =
+                processor_info.map_or_else(|| Box::new([]) as Box<[_]>, |p| p.triggers.clone());
+            (printer("r", triggers), printer("w", Box::new([])))
+        })
+        .collect();
+
+    let speed = cli_args.replay_speed.unwrap_or(1.0);
+    let mut last_timestamp_ns: Option<i64> = None;
+    for event in recording.events.iter() {
+        if cli_args.replay_step {
+            if !step_prompt() {
+                break;
+            }
+        } else if let (true, Some(last)) = (speed > 0.0, last_timestamp_ns) {
+            let gap = Duration::from_nanos((event.timestamp_ns - last).max(0) as u64);
+            thread::sleep(gap.min(REPLAY_MAX_GAP).div_f64(speed));
+        }
+        last_timestamp_ns = Some(event.timestamp_ns);
+
+        let (read_printer, write_printer) = &mut printers[event.processor];
+        match &event.data {
+            ReplayData::Rx(bytes) => read_printer.push_bytes(bytes),
+            ReplayData::Tx(bytes) => write_printer.push_bytes(bytes),
+            ReplayData::Marker(msg) => read_printer.push_marker(msg),
+        }
+    }
+    println!(
+        "> [replay_task] replayed {} events from {:?}",
+        recording.events.len(),
+        replay_path
+    );
+}
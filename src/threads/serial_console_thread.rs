@@ -1,19 +1,39 @@
 use crate::{
-    utils::ring_buf_queue::RingBufQConsumer,
+    config::ProcessorInfo,
+    utils::ring_buf_queue::{RingBufQConsumer, RingBufQProducer},
     utils::{
+        capture::CaptureSender,
         line_printer::LinePrinter,
         sync_flag::{new_sync_flag, SyncFlagAssassin, SyncFlagVictim},
+        transport::{open_transport, Transport},
         user_io::{BoxErr, BoxResult},
-    }, config::ProcessorInfo,
+        waker::{wait_readable, AsRawFd, WakeReceiver, Waker},
+    },
 };
-use serialport::SerialPort;
 use std::{
+    io::{self, ErrorKind},
     thread,
-    thread::{yield_now, JoinHandle},
+    thread::JoinHandle,
+    time::Duration,
 };
 
+pub const RECONNECT_POLL_PERIOD: Duration = Duration::from_millis(500);
+/// Upper bound on how long an idle port sleeps without any wake up.
+pub const IDLE_POLL_PERIOD: Duration = Duration::from_millis(1000);
+pub const WRITE_RETRY_PERIOD: Duration = Duration::from_millis(10);
+
+/// Where a port's traffic goes besides its `LinePrinter`.
+#[derive(Default)]
+pub struct PortTaps {
+    /// Records rx and tx bytes.
+    pub capture: Option<CaptureSender>,
+    /// Gets a copy of every received byte, eg for a bridge.
+    pub rx_fanout: Option<RingBufQProducer<u8>>,
+}
+
 pub struct SerialConsoleThread<const SIZE: usize> {
     assassin: SyncFlagAssassin,
+    waker: Waker,
     join_handle: JoinHandle<BoxResult<()>>,
 }
 
@@ -22,55 +42,212 @@ impl<const SIZE: usize> SerialConsoleThread<SIZE> {
         line_printer: LinePrinter,
         processor_info: &ProcessorInfo,
         write_consumers: Vec<RingBufQConsumer<SIZE, u8>>,
+        (waker, wake_receiver): (Waker, WakeReceiver),
+        taps: PortTaps,
     ) -> BoxResult<Self> {
-        let duration = std::time::Duration::from_millis(10);
-        let path = processor_info.port_name.clone();
-        let baud_rate = processor_info.baud_rate;
-        let builder = serialport::new(path, baud_rate).timeout(duration);
-        let serial_port = builder.open().box_err()?;
+        let serial_port = open_transport(processor_info).box_err()?;
+        let processor_info = processor_info.clone();
         let (victim, assassin) = new_sync_flag();
 
         Ok(Self {
             assassin,
+            waker,
             join_handle: thread::spawn(move || {
-                serial_console_task(victim, serial_port, write_consumers, line_printer)
+                serial_console_task(
+                    victim,
+                    processor_info,
+                    serial_port,
+                    write_consumers,
+                    wake_receiver,
+                    line_printer,
+                    taps,
+                )
             }),
         })
     }
     pub fn join(self) -> BoxResult<()> {
         self.assassin.kill_victim();
+        self.waker.wake();
         self.join_handle.join()?
     }
 }
 
+/// Timeouts are how an idle port answers a read; anything else means the
+/// device handle is gone (unplugged, powered off, or reset its USB bridge).
+fn is_disconnect(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        ErrorKind::TimedOut | ErrorKind::Interrupted | ErrorKind::WouldBlock
+    )
+}
+
+fn is_line_end(bytes: &[u8], idx: usize) -> bool {
+    match bytes[idx] {
+        b'\n' => true,
+        b'\r' => bytes.get(idx + 1) != Some(&b'\n'),
+        _ => false,
+    }
+}
+
+/// Writes `bytes` honoring the processor's inter-char and inter-line delays.
+/// Without a char delay whole lines go out in a single write. Returns how
+/// many bytes were written before any error, which includes the part of a
+/// line the port took before timing out.
+fn write_paced(
+    serial_port: &mut dyn Transport,
+    bytes: &[u8],
+    processor_info: &ProcessorInfo,
+    capture: &Option<CaptureSender>,
+) -> (usize, io::Result<()>) {
+    let char_delay = processor_info.tx_char_delay;
+    let line_delay = processor_info.tx_line_delay;
+    let mut sent = 0;
+    while sent < bytes.len() {
+        let mut end = sent + 1;
+        if char_delay.is_zero() {
+            while end < bytes.len() && (line_delay.is_zero() || !is_line_end(bytes, end - 1)) {
+                end += 1;
+            }
+        }
+        let written = match serial_port.write(&bytes[sent..end]) {
+            Ok(0) => return (sent, Err(ErrorKind::WriteZero.into())),
+            Ok(written) => written,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return (sent, Err(e)),
+        };
+        if let Some(capture) = capture {
+            capture.tx(&bytes[sent..sent + written]);
+        }
+        sent += written;
+        if sent < end {
+            continue;
+        }
+        if !char_delay.is_zero() {
+            thread::sleep(char_delay);
+        }
+        if !line_delay.is_zero() && is_line_end(bytes, end - 1) {
+            thread::sleep(line_delay);
+        }
+    }
+    (sent, Ok(()))
+}
+
+/// Polls the port list until the same device shows up again (possibly under
+/// another port name) and reopens it with the original settings. Unlisted
+/// ports are retried under their own name.
+fn reconnect(
+    victim: &SyncFlagVictim,
+    processor_info: &mut ProcessorInfo,
+) -> Option<Box<dyn Transport>> {
+    while victim.is_alive() {
+        thread::sleep(RECONNECT_POLL_PERIOD);
+        let mut candidates: Vec<_> = match processor_info.usb_port_info() {
+            None => vec![processor_info.clone()],
+            Some(_) => {
+                let Ok(procs) = ProcessorInfo::available_processors() else {
+                    continue;
+                };
+                procs
+                    .into_iter()
+                    .filter(|p| processor_info.same_device(p))
+                    .collect()
+            }
+        };
+        candidates.sort_by_key(|p| p.port_name != processor_info.port_name);
+        for candidate in candidates {
+            let port_name = std::mem::replace(&mut processor_info.port_name, candidate.port_name);
+            match open_transport(processor_info) {
+                Ok(serial_port) => return Some(serial_port),
+                Err(_) => processor_info.port_name = port_name,
+            }
+        }
+    }
+    None
+}
+
 fn serial_console_task<const SIZE: usize>(
     victim: SyncFlagVictim,
-    mut serial_port: Box<dyn SerialPort>,
+    mut processor_info: ProcessorInfo,
+    mut serial_port: Box<dyn Transport>,
     mut write_consumers: Vec<RingBufQConsumer<SIZE, u8>>,
+    wake_receiver: WakeReceiver,
     mut line_printer: LinePrinter,
+    mut taps: PortTaps,
 ) -> BoxResult<()> {
     let mut read_buf = [0u8; SIZE];
+    let mut unsent: Vec<u8> = vec![];
 
     while victim.is_alive() {
+        for notice in serial_port.take_notices() {
+            line_printer.push_marker(&notice);
+        }
+        let mut disconnected = false;
         for write_consumer in write_consumers.iter_mut() {
-            loop {
-                let write_buf = write_consumer.pop();
-                if write_buf.is_empty() {
+            while !disconnected {
+                if unsent.is_empty() {
+                    unsent.extend_from_slice(&write_consumer.pop());
+                }
+                if unsent.is_empty() {
                     break;
-                } else {
-                    for b in write_buf.iter() {
-                        let _ = serial_port.write_all(&[*b]);
-                        let dur = core::time::Duration::from_millis(1);
-                        thread::sleep(dur);
+                }
+                let (sent, res) = write_paced(
+                    serial_port.as_mut(),
+                    &unsent,
+                    &processor_info,
+                    &taps.capture,
+                );
+                unsent.drain(..sent);
+                if let Err(e) = res {
+                    // a timed out write (eg held off by flow control) is retried later
+                    disconnected = is_disconnect(&e);
+                    break;
+                }
+            }
+        }
+
+        // sleep until the port has data or a producer pushed more writes
+        if !disconnected {
+            let fds = [serial_port.as_raw_fd(), wake_receiver.as_raw_fd()];
+            let timeout = match unsent.is_empty() {
+                true => IDLE_POLL_PERIOD,
+                false => WRITE_RETRY_PERIOD,
+            };
+            let ready = wait_readable(&fds, timeout).box_err()?;
+            if ready[1] {
+                wake_receiver.drain();
+            }
+            if ready[0] {
+                match serial_port.read(&mut read_buf) {
+                    Ok(count) => {
+                        if let Some(capture) = &taps.capture {
+                            capture.rx(&read_buf[..count]);
+                        }
+                        if let Some(rx_fanout) = &mut taps.rx_fanout {
+                            rx_fanout.push(&read_buf[..count]);
+                        }
+                        line_printer.push_bytes(&read_buf[..count]);
                     }
+                    Err(e) if is_disconnect(&e) => disconnected = true,
+                    Err(_) => {}
                 }
             }
+        }
 
-            if let Ok(count) = serial_port.read(&mut read_buf) {
-                line_printer.push_bytes(&read_buf[..count]);
+        if disconnected {
+            line_printer.push_marker(&format!(
+                "> [serial_console_task] {} disconnected from {}",
+                processor_info.processor_name, processor_info.port_name
+            ));
+            drop(serial_port);
+            match reconnect(&victim, &mut processor_info) {
+                Some(reopened) => serial_port = reopened,
+                None => break,
             }
+            line_printer.push_marker(&format!(
+                "> [serial_console_task] {} reconnected on {}",
+                processor_info.processor_name, processor_info.port_name
+            ));
         }
-        yield_now();
     }
 
     Ok(())
@@ -0,0 +1,290 @@
+use crate::{
+    threads::{
+        main_thread::{set_thread_priority, USER_CONSOLE_THREAD_PRIORITY},
+        user_console_thread::ProcessorUserConsoleWriter,
+    },
+    utils::{
+        console_output::PaneLine,
+        sync_flag::SyncFlagVictim,
+        terminal::{
+            move_to, read_keys, terminal_size, truncate_visible, Key, RawMode, CLEAR_SCREEN,
+            CLEAR_TO_EOL, ENTER_ALT_SCREEN, LEAVE_ALT_SCREEN, RESET_STYLE, REVERSE_VIDEO,
+        },
+        waker::wait_readable,
+    },
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::mpsc::Receiver,
+    time::Duration,
+};
+
+pub const TUI_SCROLLBACK: usize = 10_000;
+pub const TUI_POLL_PERIOD: Duration = Duration::from_millis(30);
+
+struct Pane {
+    name: String,
+    lines: VecDeque<String>,
+    /// Lines scrolled back from the tail; 0 follows new output.
+    scroll: usize,
+}
+
+impl Pane {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == TUI_SCROLLBACK {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.lines.len());
+        }
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        let scroll = self.scroll.saturating_add_signed(delta);
+        self.scroll = scroll.min(self.lines.len());
+    }
+}
+
+struct InputLine {
+    chars: Vec<char>,
+    cursor: usize,
+    /// Position while browsing the active processor's cmd history.
+    history_idx: Option<usize>,
+}
+
+impl InputLine {
+    fn set(&mut self, line: &str) {
+        self.chars = line.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.history_idx = None;
+        self.chars.drain(..).collect()
+    }
+}
+
+enum KeyRes {
+    Redraw,
+    Exit,
+}
+
+struct Tui<'w> {
+    writers: &'w mut [ProcessorUserConsoleWriter],
+    panes: Vec<Pane>,
+    active: usize,
+    input: InputLine,
+    /// The latest message that belongs to no processor.
+    status: String,
+    size: (usize, usize),
+}
+
+impl<'w> Tui<'w> {
+    /// Rows above the status row and the input line.
+    fn pane_area(&self) -> usize {
+        let (rows, _) = self.size;
+        rows.saturating_sub(2)
+    }
+
+    fn pane_height(&self) -> usize {
+        (self.pane_area() / self.panes.len()).max(2)
+    }
+
+    fn handle_key(&mut self, key: Key) -> KeyRes {
+        let input = &mut self.input;
+        let writer = &mut self.writers[self.active];
+        match key {
+            Key::Ctrl('c') => return KeyRes::Exit,
+            Key::Ctrl('d') | Key::Tab => {
+                self.active = (self.active + 1) % self.writers.len();
+                input.history_idx = None;
+            }
+            Key::Enter => writer.send_line(input.take()),
+            Key::Char(ch) => {
+                input.chars.insert(input.cursor, ch);
+                input.cursor += 1;
+            }
+            Key::Backspace if input.cursor > 0 => {
+                input.cursor -= 1;
+                input.chars.remove(input.cursor);
+            }
+            Key::Delete if input.cursor < input.chars.len() => {
+                input.chars.remove(input.cursor);
+            }
+            Key::Left => input.cursor = input.cursor.saturating_sub(1),
+            Key::Right => input.cursor = (input.cursor + 1).min(input.chars.len()),
+            Key::Home | Key::Ctrl('a') => input.cursor = 0,
+            Key::End | Key::Ctrl('e') => input.cursor = input.chars.len(),
+            Key::Ctrl('u') => {
+                input.take();
+            }
+            Key::Up => {
+                let idx = match input.history_idx {
+                    Some(idx) => idx.saturating_sub(1),
+                    None => writer.history_len().saturating_sub(1),
+                };
+                if let Some(entry) = writer.history_entry(idx) {
+                    input.set(&entry.clone());
+                    input.history_idx = Some(idx);
+                }
+            }
+            Key::Down => match input.history_idx {
+                Some(idx) if idx + 1 < writer.history_len() => {
+                    input.set(&writer.history_entry(idx + 1).unwrap().clone());
+                    input.history_idx = Some(idx + 1);
+                }
+                Some(_) => {
+                    input.take();
+                }
+                None => {}
+            },
+            Key::PageUp => {
+                let page = self.pane_height() as isize / 2;
+                self.panes[self.active].scroll_by(page);
+            }
+            Key::PageDown => {
+                let page = self.pane_height() as isize / 2;
+                self.panes[self.active].scroll_by(-page);
+            }
+            _ => {}
+        }
+        KeyRes::Redraw
+    }
+
+    fn draw(&self) -> String {
+        let (rows, cols) = self.size;
+        let area = self.pane_area();
+        let height = self.pane_height();
+        let mut frame = String::new();
+        for (idx, pane) in self.panes.iter().enumerate() {
+            let top = idx * height;
+            if top >= area {
+                break;
+            }
+            let marker = if idx == self.active { '*' } else { ' ' };
+            let mut title = format!("{} {} ", marker, pane.name);
+            if pane.scroll > 0 {
+                title.push_str(&format!("[scrolled back {}] ", pane.scroll));
+            }
+            let title: String = title
+                .chars()
+                .chain(std::iter::repeat('-'))
+                .take(cols)
+                .collect();
+            frame.push_str(&move_to(top, 0));
+            frame.push_str(REVERSE_VIDEO);
+            frame.push_str(&title);
+            frame.push_str(RESET_STYLE);
+
+            let body_rows = (height - 1).min(area - top - 1);
+            let end = pane.lines.len() - pane.scroll;
+            let start = end.saturating_sub(body_rows);
+            for row in 0..body_rows {
+                frame.push_str(&move_to(top + 1 + row, 0));
+                if let Some(line) = pane.lines.get(start + row).filter(|_| start + row < end) {
+                    frame.push_str(&truncate_visible(line, cols));
+                    frame.push_str(RESET_STYLE);
+                }
+                frame.push_str(CLEAR_TO_EOL);
+            }
+        }
+
+        frame.push_str(&move_to(rows.saturating_sub(2), 0));
+        frame.push_str(&truncate_visible(&self.status, cols));
+        frame.push_str(RESET_STYLE);
+        frame.push_str(CLEAR_TO_EOL);
+
+        let prompt = format!("{}> ", self.panes[self.active].name);
+        let avail = cols.saturating_sub(prompt.chars().count() + 1).max(1);
+        let offset = self.input.cursor.saturating_sub(avail);
+        frame.push_str(&move_to(rows - 1, 0));
+        frame.push_str(&prompt);
+        frame.extend(self.input.chars.iter().skip(offset).take(avail));
+        frame.push_str(CLEAR_TO_EOL);
+        let cursor_col = prompt.chars().count() + self.input.cursor - offset;
+        frame.push_str(&move_to(rows - 1, cursor_col));
+        frame
+    }
+}
+
+/// Full-screen alternative to `user_console_task`: one scrollback pane per
+/// processor, a status row with the latest task message, and a single input
+/// line bound to the active processor.
+///
+/// Tab or Ctrl-D switches processor, PgUp/PgDn scroll the active pane,
+/// Up/Down browse its cmd history and Ctrl-C exits.
+pub fn tui_console_task(
+    victim: SyncFlagVictim,
+    writers: &mut [ProcessorUserConsoleWriter],
+    pane_receiver: Receiver<PaneLine>,
+) -> io::Result<()> {
+    set_thread_priority::<USER_CONSOLE_THREAD_PRIORITY>();
+    let raw_mode = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    write!(stdout, "{}{}", ENTER_ALT_SCREEN, CLEAR_SCREEN)?;
+
+    let panes = writers
+        .iter()
+        .map(|w| Pane {
+            name: w.processor_name().to_string(),
+            lines: VecDeque::new(),
+            scroll: 0,
+        })
+        .collect();
+    let mut tui = Tui {
+        writers,
+        panes,
+        active: 0,
+        input: InputLine {
+            chars: vec![],
+            cursor: 0,
+            history_idx: None,
+        },
+        status: String::new(),
+        size: terminal_size().unwrap_or((24, 80)),
+    };
+
+    let mut dirty = true;
+    while victim.is_alive() {
+        while let Ok(PaneLine { pane, line }) = pane_receiver.try_recv() {
+            match pane {
+                Some(pane) => tui.panes[pane].push(line),
+                None => tui.status = line,
+            }
+            dirty = true;
+        }
+
+        let size = terminal_size().unwrap_or(tui.size);
+        if size != tui.size {
+            tui.size = size;
+            write!(stdout, "{}", CLEAR_SCREEN)?;
+            dirty = true;
+        }
+        if dirty {
+            write!(stdout, "{}", tui.draw())?;
+            stdout.flush()?;
+            dirty = false;
+        }
+
+        if wait_readable(&[0], TUI_POLL_PERIOD)?[0] {
+            for key in read_keys()? {
+                match tui.handle_key(key) {
+                    KeyRes::Redraw => dirty = true,
+                    KeyRes::Exit => {
+                        write!(stdout, "{}", LEAVE_ALT_SCREEN)?;
+                        drop(raw_mode);
+                        println!("> [tui_console_task] ended");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+    write!(stdout, "{}", LEAVE_ALT_SCREEN)?;
+    drop(raw_mode);
+    println!("> [tui_console_task] ended without user input");
+    Ok(())
+}
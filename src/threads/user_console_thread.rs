@@ -1,15 +1,29 @@
 use crate::{
-    config::ProcessorInfo,
-    threads::main_thread::{set_thread_priority, USER_CONSOLE_THREAD_PRIORITY},
+    config::{DisplayMode, LineEnding, ProcessorInfo},
+    threads::{
+        main_thread::{set_thread_priority, USER_CONSOLE_THREAD_PRIORITY},
+        serial_console_thread::IDLE_POLL_PERIOD,
+    },
     utils::{
-        line_printer::LinePrinter, ring_buf_queue::RingBufQProducer, sync_flag::SyncFlagVictim,
+        line_printer::LinePrinter,
+        ring_buf_queue::RingBufQProducer,
+        sync_flag::SyncFlagVictim,
+        terminal::{catch_sigint, sigint_caught},
+        user_io::{box_err_msg, BoxResult, RaisedError},
     },
 };
 use rustyline::{error::ReadlineError, Editor};
-use std::path::{Path, PathBuf};
+use std::{
+    io::{stdin, IsTerminal},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError},
+    thread,
+};
 
 pub struct ProcessorUserConsoleWriter {
     processor_name: String,
+    tx_line_ending: LineEnding,
+    display: DisplayMode,
     history_path: PathBuf,
     editor: Editor<()>,
     write_producer: RingBufQProducer<u8>,
@@ -49,6 +63,8 @@ impl ProcessorUserConsoleWriter {
             write_producer,
             line_printer,
             processor_name: processor_info.processor_name.clone(),
+            tx_line_ending: processor_info.tx_line_ending,
+            display: processor_info.display,
         }
     }
     pub fn readline(&mut self) -> ReadLineRes {
@@ -62,6 +78,40 @@ impl ProcessorUserConsoleWriter {
             }
         }
     }
+    pub fn processor_name(&self) -> &str {
+        &self.processor_name
+    }
+
+    /// Entry `idx` of the cmd history, oldest first.
+    pub fn history_entry(&self, idx: usize) -> Option<&String> {
+        self.editor.history().get(idx)
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.editor.history().len()
+    }
+
+    /// Records `line` in the history, transmits it, and echoes it to the log.
+    /// In hex display mode the line is a hex byte string sent as is, without
+    /// a line ending.
+    pub fn send_line(&mut self, line: String) {
+        self.editor.add_history_entry(&line);
+        let sent = send_line(
+            &line,
+            self.display,
+            self.tx_line_ending,
+            &mut self.write_producer,
+            &mut self.line_printer,
+        );
+        if let Err(e) = sent {
+            self.line_printer.push_marker(&format!(
+                "> [user_console_task] not sending {:?}: {}",
+                line,
+                box_err_msg(&e)
+            ));
+        }
+    }
+
     pub fn save_history(mut self) {
         if let Err(e) = self.editor.save_history(&self.history_path) {
             println!(
@@ -77,18 +127,60 @@ impl ProcessorUserConsoleWriter {
     }
 }
 
+/// Transmits `line` with `tx_line_ending` and echoes it to `write_printer`.
+/// In hex display mode the line is a hex byte string sent as is, without a
+/// line ending.
+pub fn send_line(
+    line: &str,
+    display: DisplayMode,
+    tx_line_ending: LineEnding,
+    write_producer: &mut RingBufQProducer<u8>,
+    write_printer: &mut LinePrinter,
+) -> BoxResult<()> {
+    if let DisplayMode::Hex = display {
+        let bytes = parse_hex_bytes(line)?;
+        write_producer.push(bytes.as_slice());
+        write_printer.push_bytes(&bytes);
+        return Ok(());
+    }
+    let tx_line = format!("{}{}", line, tx_line_ending.as_str());
+    write_producer.push(tx_line.as_bytes());
+    // always end the echoed line, even if nothing terminates it on the wire
+    write_printer.push_str(&format!("{}\r", line));
+    Ok(())
+}
+
+/// Parses `\x7e\x01\x00`, `7e 01 00`, `0x7e,0x01` or `7e0100` into bytes.
+pub fn parse_hex_bytes(line: &str) -> BoxResult<Vec<u8>> {
+    let mut bytes = vec![];
+    let line = line.replace("\\x", " ");
+    for token in line.split(|ch: char| ch.is_whitespace() || ch == ',') {
+        let token = token.strip_prefix("0x").unwrap_or(token);
+        if token.len() % 2 != 0 {
+            return Err(RaisedError::new("hex bytes need two digits each"));
+        }
+        for idx in (0..token.len()).step_by(2) {
+            let byte = token
+                .get(idx..idx + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| RaisedError::new("not a hex byte string"))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
+
 pub fn user_console_task(victim: SyncFlagVictim, writers: &mut [ProcessorUserConsoleWriter]) {
     set_thread_priority::<USER_CONSOLE_THREAD_PRIORITY>();
+    if !stdin().is_terminal() {
+        piped_console_task(victim, &mut writers[0]);
+        return;
+    }
     let mut processor_idx = 0;
     let mut writer = &mut writers[processor_idx];
     while victim.is_alive() {
         match writer.readline() {
-            ReadLineRes::Line(mut line) => {
-                writer.editor.add_history_entry(&line);
-                line.push('\r');
-                writer.write_producer.push(line.as_bytes());
-                writer.line_printer.push_str(&line);
-            }
+            ReadLineRes::Line(line) => writer.send_line(line),
             ReadLineRes::NextProcessor => {
                 processor_idx += 1;
                 processor_idx %= writers.len();
@@ -107,3 +199,66 @@ pub fn user_console_task(victim: SyncFlagVictim, writers: &mut [ProcessorUserCon
     }
     println!("> [user_console_task] ended without user input");
 }
+
+/// Sends lines piped to stdin to the first processor. Running out of input
+/// does not end the session, SIGINT or the control socket's `quit` does.
+fn piped_console_task(victim: SyncFlagVictim, writer: &mut ProcessorUserConsoleWriter) {
+    catch_sigint();
+    let (line_sender, line_receiver) = channel();
+    // stdin reads can not be interrupted, so the reader is left blocked at exit
+    thread::spawn(move || {
+        for line in stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line_sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut line_receiver = Some(line_receiver);
+    while victim.is_alive() && !sigint_caught() {
+        let Some(receiver) = &line_receiver else {
+            thread::sleep(IDLE_POLL_PERIOD);
+            continue;
+        };
+        match receiver.recv_timeout(IDLE_POLL_PERIOD) {
+            Ok(line) => writer.send_line(line),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                println!("> [user_console_task] stdin closed, waiting for Ctrl-C or quit");
+                line_receiver = None;
+            }
+        }
+    }
+    println!("> [user_console_task] ended");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_byte_strings() {
+        let bytes = [0x7e, 0x01, 0x00];
+        let lines = [
+            r"\x7e\x01\x00",
+            "7e 01 00",
+            "0x7e,0x01,0x00",
+            "7e0100",
+            "7E 01 00 ",
+        ];
+        for line in lines {
+            assert_eq!(parse_hex_bytes(line).unwrap(), bytes, "{:?}", line);
+        }
+        assert!(parse_hex_bytes("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_hex_byte_strings() {
+        for line in ["7e 1", "7e0", r"\x7", "0x7g", "zz", "7e \u{e9}e"] {
+            assert!(parse_hex_bytes(line).is_err(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    sync::mpsc::Sender,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// First bytes of every capture file; the last byte is the format version.
+pub const CAPTURE_MAGIC: [u8; 8] = *b"JFCAP\0\0\x01";
+
+/// Record layout, all integers little endian:
+///
+/// | bytes | field                                   |
+/// |-------|-----------------------------------------|
+/// | 1     | kind: 0 processor name, 1 rx, 2 tx      |
+/// | 1     | processor index                         |
+/// | 8     | timestamp, ns since the unix epoch      |
+/// | 4     | payload length                          |
+/// | n     | payload                                 |
+pub const RECORD_HEADER_LEN: usize = 14;
+/// Processors a capture can tell apart with its one byte processor index.
+pub const MAX_CAPTURE_PROCESSORS: usize = u8::MAX as usize + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordKind {
+    /// Names the processor an index refers to, written once per processor
+    /// at the start of the file.
+    Name,
+    Rx,
+    Tx,
+}
+
+impl RecordKind {
+    fn to_u8(self) -> u8 {
+        match self {
+            RecordKind::Name => 0,
+            RecordKind::Rx => 1,
+            RecordKind::Tx => 2,
+        }
+    }
+
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(RecordKind::Name),
+            1 => Some(RecordKind::Rx),
+            2 => Some(RecordKind::Tx),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CaptureRecord {
+    pub kind: RecordKind,
+    pub processor: u8,
+    pub timestamp_ns: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn new(kind: RecordKind, processor: u8, bytes: &[u8]) -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            kind,
+            processor,
+            timestamp_ns: since_epoch.as_nanos() as u64,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0] = self.kind.to_u8();
+        header[1] = self.processor;
+        header[2..10].copy_from_slice(&self.timestamp_ns.to_le_bytes());
+        header[10..14].copy_from_slice(&(self.bytes.len() as u32).to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&self.bytes)
+    }
+
+    /// Reads the next record, or `None` at a clean end of file.
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let kind = RecordKind::from_u8(header[0])
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "unknown capture record"))?;
+        let timestamp_ns = u64::from_le_bytes(header[2..10].try_into().unwrap());
+        let len = u32::from_le_bytes(header[10..14].try_into().unwrap()) as usize;
+        // the length may be corrupt, so the payload only grows as it is read
+        let mut bytes = vec![];
+        reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < len {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        Ok(Some(Self {
+            kind,
+            processor: header[1],
+            timestamp_ns,
+            bytes,
+        }))
+    }
+}
+
+/// Checks `reader` starts with `CAPTURE_MAGIC`.
+pub fn read_capture_magic(reader: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0u8; CAPTURE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    match magic == CAPTURE_MAGIC {
+        true => Ok(()),
+        false => Err(io::Error::new(ErrorKind::InvalidData, "not a capture file")),
+    }
+}
+
+/// One processor's handle on the capture thread.
+#[derive(Clone)]
+pub struct CaptureSender {
+    pub processor: u8,
+    pub sender: Sender<CaptureRecord>,
+}
+
+impl CaptureSender {
+    pub fn rx(&self, bytes: &[u8]) {
+        let _ = self
+            .sender
+            .send(CaptureRecord::new(RecordKind::Rx, self.processor, bytes));
+    }
+
+    pub fn tx(&self, bytes: &[u8]) {
+        let _ = self
+            .sender
+            .send(CaptureRecord::new(RecordKind::Tx, self.processor, bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_records() {
+        let mut file = vec![];
+        let record = CaptureRecord::new(RecordKind::Tx, 255, b"hello");
+        record.write_to(&mut file).unwrap();
+        let mut reader = file.as_slice();
+        let read = CaptureRecord::read_from(&mut reader).unwrap().unwrap();
+        assert_eq!(read.kind, RecordKind::Tx);
+        assert_eq!(read.processor, 255);
+        assert_eq!(read.timestamp_ns, record.timestamp_ns);
+        assert_eq!(read.bytes, b"hello");
+        assert!(CaptureRecord::read_from(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn reports_a_record_longer_than_the_file() {
+        let mut file = vec![];
+        let record = CaptureRecord::new(RecordKind::Rx, 0, b"cut");
+        record.write_to(&mut file).unwrap();
+        // claim 4 GiB of payload
+        file[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let e = CaptureRecord::read_from(&mut file.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+}
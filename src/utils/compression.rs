@@ -0,0 +1,114 @@
+//! Compressed logs: gzip and zstd writers, and a reader that tells either
+//! apart from plain text by the first bytes of the file.
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// zstd's default level; logs compress well at any level, so favor speed.
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// Suffix of a compressed file, appended to the plain file name.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => ".gz",
+            Codec::Zstd => ".zst",
+        }
+    }
+
+    /// `path` with this codec's suffix appended, eg `a.log.gz`.
+    pub fn path(self, path: &Path) -> PathBuf {
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(self.extension());
+        PathBuf::from(compressed)
+    }
+}
+
+/// `file_name` without a compressed file suffix, eg `a.log` for `a.log.zst`.
+pub fn strip_codec_extension(file_name: &str) -> &str {
+    [Codec::Gzip, Codec::Zstd]
+        .iter()
+        .find_map(|codec| file_name.strip_suffix(codec.extension()))
+        .unwrap_or(file_name)
+}
+
+/// Compressing writer; `finish` ends the stream. `flush` makes everything
+/// written so far decodable, even if the stream is never finished.
+pub enum Encoder<W: Write> {
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(codec: Codec, inner: W) -> io::Result<Self> {
+        match codec {
+            Codec::Gzip => Ok(Encoder::Gzip(GzEncoder::new(inner, Compression::default()))),
+            Codec::Zstd => Ok(Encoder::Zstd(zstd::Encoder::new(inner, ZSTD_LEVEL)?)),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        match self {
+            Encoder::Gzip(encoder) => encoder.get_ref(),
+            Encoder::Zstd(encoder) => encoder.get_ref(),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Opens `path` for reading, decompressing it if it is gzipped or zstd
+/// compressed. A stream cut short, eg the log of a session that is still
+/// running, reads up to the cut and then fails with `UnexpectedEof`.
+pub fn open_maybe_compressed(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let head = file.fill_buf()?;
+    if head.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(file)?))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Compresses `src` to `dst` in one go.
+pub fn compress_file(src: &Path, dst: &Path, codec: Codec) -> io::Result<()> {
+    let mut reader = File::open(src)?;
+    let mut encoder = Encoder::new(codec, File::create(dst)?)?;
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
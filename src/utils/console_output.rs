@@ -0,0 +1,118 @@
+use crate::{
+    config::{AnsiMode, HighlightRule},
+    utils::terminal::{keep_sgr_only, Color, RESET_STYLE},
+};
+use std::sync::{mpsc::Sender, Arc};
+
+/// A console line bound for the split-pane terminal UI: one processor's
+/// pane, or the status row when `pane` is `None`.
+pub struct PaneLine {
+    pub pane: Option<usize>,
+    pub line: String,
+}
+
+/// Where a `LinePrinter` or a task's status messages are shown: plain
+/// stdout, or part of the TUI.
+#[derive(Clone)]
+pub enum ConsoleOutput {
+    Stdout,
+    Pane {
+        pane: usize,
+        sender: Sender<PaneLine>,
+    },
+    /// Messages that belong to no processor, eg log rotation.
+    Status(Sender<PaneLine>),
+}
+
+/// Console-only coloring; the log file always receives plain lines.
+#[derive(Clone, Default)]
+pub struct ConsoleStyle {
+    pub color: Option<Color>,
+    pub highlights: Arc<[HighlightRule]>,
+    pub ansi: AnsiMode,
+}
+
+impl ConsoleStyle {
+    /// Colors `prefix` with the processor color and `rest` with the first
+    /// highlight rule matching `text`.
+    pub fn paint(&self, prefix: &str, rest: &str, text: &str) -> String {
+        let highlight = self.highlights.iter().find(|h| h.pattern.is_match(text));
+        let mut painted = String::new();
+        match self.color {
+            Some(color) => painted.push_str(&format!("{}{}{}", color.fg(), prefix, RESET_STYLE)),
+            None => painted.push_str(prefix),
+        }
+        painted.push(' ');
+        match highlight {
+            Some(h) => painted.push_str(&format!("{}{}{}", h.color.fg(), rest, RESET_STYLE)),
+            None => painted.push_str(rest),
+        }
+        painted
+    }
+
+    /// The console form of a line: `raw` still holds the processor's escape
+    /// sequences, `plain` has them stripped.
+    pub fn ansi_text(&self, raw: &str, plain: &str) -> String {
+        match self.ansi {
+            AnsiMode::Strip => plain.to_string(),
+            AnsiMode::Pass => {
+                let styled = keep_sgr_only(raw);
+                // don't let an unterminated color bleed into the next line
+                match styled.contains('\x1b') {
+                    true => format!("{}{}", styled, RESET_STYLE),
+                    false => styled,
+                }
+            }
+            AnsiMode::Render => raw.replace('\x1b', "^["),
+        }
+    }
+}
+
+impl ConsoleOutput {
+    pub fn print(&self, line: String) {
+        match self {
+            ConsoleOutput::Stdout => println!("{}", line),
+            ConsoleOutput::Pane { pane, sender } => {
+                let _ = sender.send(PaneLine {
+                    pane: Some(*pane),
+                    line,
+                });
+            }
+            ConsoleOutput::Status(sender) => {
+                let _ = sender.send(PaneLine { pane: None, line });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(ansi: AnsiMode) -> ConsoleStyle {
+        ConsoleStyle {
+            ansi,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn passes_only_colors_through() {
+        let raw = "\x1b[2J\x1b[1;1H\x1b[31mred\x1b[0m \x1b[5;20r\x1b]0;title\x07done";
+        let text = style(AnsiMode::Pass).ansi_text(raw, "red done");
+        assert_eq!(text, format!("\x1b[31mred\x1b[0m done{}", RESET_STYLE));
+        assert!(!text.contains("\x1b[2J"));
+        let text = style(AnsiMode::Pass).ansi_text("\x1b[2Jplain", "plain");
+        assert_eq!(text, "plain");
+    }
+
+    #[test]
+    fn strips_or_renders_every_sequence() {
+        let raw = "\x1b[2J\x1b[31mred";
+        assert_eq!(style(AnsiMode::Strip).ansi_text(raw, "red"), "red");
+        assert_eq!(
+            style(AnsiMode::Render).ansi_text(raw, "red"),
+            "^[[2J^[[31mred"
+        );
+    }
+}
@@ -1,19 +1,88 @@
-use crate::utils::ring_buf_queue::RingBufQProducer;
-use chrono::Utc;
-use std::sync::mpsc::Sender;
+use crate::{
+    config::{DisplayMode, LineEnding, TriggerRule},
+    utils::{
+        console_output::{ConsoleOutput, ConsoleStyle},
+        ring_buf_queue::RingBufQProducer,
+        terminal::{escape_len, visible_to_raw_idx},
+    },
+};
+use chrono::{NaiveDateTime, Utc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::Sender,
+};
 
-pub const DATE_TIME_FMT: &'static str = "%y-%m-%d %H:%M:%S%.3f";
+pub const DATE_TIME_FMT: &str = "%y-%m-%d %H:%M:%S%.3f";
+/// Length of a `DATE_TIME_FMT` timestamp, eg `22-07-04 13:05:09.123`.
+pub const LOG_TIMESTAMP_LEN: usize = 21;
+/// Longer escape sequences are taken to be line noise and dropped.
+pub const MAX_ESCAPE_LEN: usize = 64;
+/// Bytes per row of a hex dump.
+pub const HEX_ROW_LEN: usize = 16;
+
+/// A write queue into a processor's serial thread.
+pub struct WriteRoute {
+    pub write_producer: RingBufQProducer<u8>,
+    pub tx_line_ending: LineEnding,
+}
+
+/// Write queues into other processors' serial threads, keyed by `processor_name`.
+pub type WriteRoutes = HashMap<String, WriteRoute>;
+
+pub struct LogLine<'l> {
+    pub prefix: &'l str,
+    pub timestamp_ns: i64,
+    pub complete: char,
+    pub text: &'l str,
+}
+
+/// Splits a `file_logger_task` line, `<prefix> <timestamp> <complete> <text>`.
+/// The prefix may itself contain spaces, so the timestamp is searched for.
+pub fn parse_log_line(line: &str) -> Option<LogLine<'_>> {
+    for (idx, _) in line.match_indices(' ') {
+        let ts_end = idx + 1 + LOG_TIMESTAMP_LEN;
+        let ts = line.get(idx + 1..ts_end)?;
+        let Ok(ts) = NaiveDateTime::parse_from_str(ts, DATE_TIME_FMT) else {
+            continue;
+        };
+        let rest = &line[ts_end..];
+        return Some(LogLine {
+            prefix: &line[..idx],
+            timestamp_ns: ts.and_utc().timestamp_nanos_opt().unwrap_or_default(),
+            complete: rest.chars().nth(1)?,
+            text: rest.get(3..).unwrap_or(""),
+        });
+    }
+    None
+}
 
 pub struct LinePrinter {
     console_timestamp: String,
     log_timestamp: String,
+    /// The current line with escape sequences stripped.
     buffer: String,
+    /// The current line as received, escape sequences included.
+    raw_buffer: String,
+    /// An escape sequence split across reads.
+    escape: String,
     prefix: String,
     complete: char,
     last_char: Option<char>,
     line_width: usize,
     line_sender: Sender<String>,
-    write_producer: Option<RingBufQProducer<u8>>,
+    console: ConsoleOutput,
+    console_style: ConsoleStyle,
+    write_routes: WriteRoutes,
+    triggers: Box<[ArmedTrigger]>,
+    recent_lines: VecDeque<String>,
+    display: DisplayMode,
+    /// Bytes dumped so far in hex display mode.
+    hex_offset: u64,
+}
+
+struct ArmedTrigger {
+    rule: TriggerRule,
+    lines_since_fired: usize,
 }
 
 impl LinePrinter {
@@ -25,16 +94,23 @@ impl LinePrinter {
 }
 
 macro_rules! send_split {
-    ($self: ident, $buffer: expr) => {
+    ($self: ident, $buffer: expr, $raw_buffer: expr) => {
         $self.timestamp_now();
-        println!(
-            "{} {} {} {}",
-            $self.prefix, $self.console_timestamp, $self.complete, $buffer
-        );
+        let console_text = $self.console_style.ansi_text($raw_buffer, $buffer);
+        $self.console.print($self.console_style.paint(
+            &$self.prefix,
+            &format!(
+                "{} {} {}",
+                $self.console_timestamp, $self.complete, console_text
+            ),
+            $buffer,
+        ));
         let _ = $self.line_sender.send(format!(
             "{} {} {} {}",
             $self.prefix, $self.log_timestamp, $self.complete, $buffer
         ));
+        let line = $buffer.to_string();
+        $self.fire_triggers(line);
     };
 }
 
@@ -43,25 +119,55 @@ impl LinePrinter {
         prefix: String,
         line_width: usize,
         line_sender: Sender<String>,
-        write_producer: Option<RingBufQProducer<u8>>,
+        console: ConsoleOutput,
+        console_style: ConsoleStyle,
+        write_routes: WriteRoutes,
+        triggers: Box<[TriggerRule]>,
     ) -> Self {
         let mut line_printer = Self {
             prefix,
             log_timestamp: String::new(),
             console_timestamp: String::new(),
             buffer: String::new(),
+            raw_buffer: String::new(),
+            escape: String::new(),
             complete: '|',
             line_width,
             last_char: None,
             line_sender,
-            write_producer,
+            console,
+            console_style,
+            write_routes,
+            triggers: triggers
+                .into_vec()
+                .into_iter()
+                .map(|rule| ArmedTrigger {
+                    lines_since_fired: rule.lines,
+                    rule,
+                })
+                .collect(),
+            recent_lines: VecDeque::new(),
+            display: DisplayMode::Text,
+            hex_offset: 0,
         };
         line_printer.timestamp_now();
         line_printer
     }
 
+    pub fn set_display(&mut self, display: DisplayMode) {
+        self.display = display;
+    }
+
     pub fn push_str(&mut self, lines: &str) {
         for ch in lines.chars() {
+            if ch == '\x1b' || !self.escape.is_empty() {
+                if let '\r' | '\n' = ch {
+                    self.escape.clear();
+                } else {
+                    self.push_escape_char(ch);
+                    continue;
+                }
+            }
             if let '\r' | '\n' = ch {
                 let last_char = self.last_char;
                 self.last_char = Some(ch);
@@ -70,9 +176,10 @@ impl LinePrinter {
                         continue;
                     }
                 }
-                send_split!(self, &self.buffer);
+                send_split!(self, &self.buffer, &self.raw_buffer);
                 self.complete = '|';
                 self.buffer.clear();
+                self.raw_buffer.clear();
             } else {
                 if self.buffer.len() >= self.line_width {
                     let mut last_space_idx = None;
@@ -84,8 +191,14 @@ impl LinePrinter {
                     match last_space_idx {
                         Some(0) => {}
                         Some(last_space_idx) => {
-                            send_split!(self, &self.buffer[..last_space_idx]);
+                            let raw_idx = visible_to_raw_idx(&self.raw_buffer, last_space_idx);
+                            send_split!(
+                                self,
+                                &self.buffer[..last_space_idx],
+                                &self.raw_buffer[..raw_idx]
+                            );
                             self.buffer = self.buffer[last_space_idx..].to_string();
+                            self.raw_buffer = self.raw_buffer[raw_idx..].to_string();
                             self.complete = ' ';
                             self.last_char = None;
                         }
@@ -93,19 +206,188 @@ impl LinePrinter {
                     }
                 }
                 self.buffer.push(ch);
+                self.raw_buffer.push(ch);
                 self.last_char = None;
             }
         }
+    }
+
+    /// Collects an escape sequence; once complete it goes into the raw buffer
+    /// only, so it never reaches the log or counts toward the line width.
+    fn push_escape_char(&mut self, ch: char) {
+        // a new escape abandons an unfinished one, except for an OSC's `ESC \`
+        if ch == '\x1b' && !self.escape.starts_with("\x1b]") {
+            self.escape.clear();
+        }
+        self.escape.push(ch);
+        if let Some(len) = escape_len(&self.escape) {
+            self.raw_buffer.push_str(&self.escape[..len]);
+            self.escape.clear();
+        } else if self.escape.len() > MAX_ESCAPE_LEN {
+            self.escape.clear();
+        }
+    }
+
+    /// Matches each trigger against the completed lines it has not fired on
+    /// yet, so a multi-line match only fires once.
+    fn fire_triggers(&mut self, line: String) {
+        if self.triggers.is_empty() {
+            return;
+        }
+        let max_lines = self.triggers.iter().map(|t| t.rule.lines).max();
+        self.recent_lines.push_back(line);
+        while self.recent_lines.len() > max_lines.unwrap_or(0) {
+            self.recent_lines.pop_front();
+        }
 
-        let wp: &mut Option<_> = &mut self.write_producer;
-        if let Some(wp) = wp {
-            if lines.contains("IPC Comm Failure") {
-                println!("> [line_printer] ipc comm failure");
-                wp.push("t ipcwdg\r".as_bytes())
+        for trigger in self.triggers.iter_mut() {
+            trigger.lines_since_fired += 1;
+            let window_len = trigger.lines_since_fired.min(trigger.rule.lines);
+            let window_start = self.recent_lines.len().saturating_sub(window_len);
+            let window = Vec::from_iter(self.recent_lines.range(window_start..).cloned());
+            let window = window.join("\n");
+            let Some(captures) = trigger.rule.pattern.captures(&window) else {
+                continue;
+            };
+            let mut response = String::new();
+            captures.expand(&trigger.rule.response, &mut response);
+            let mut response = response.trim_end_matches(['\r', '\n']).to_string();
+            self.console.print(format!(
+                "> [line_printer] {:?} triggered {:?} to {}",
+                trigger.rule.pattern.as_str(),
+                response,
+                trigger.rule.target
+            ));
+            if let Some(route) = self.write_routes.get_mut(&trigger.rule.target) {
+                response.push_str(route.tx_line_ending.as_str());
+                route.write_producer.push(response.as_bytes());
             }
+            trigger.lines_since_fired = 0;
         }
     }
+
+    /// Logs a status line that is not processor output, eg a disconnect
+    /// notice. Markers use `#` in place of the usual `|` completion char.
+    pub fn push_marker(&mut self, msg: &str) {
+        self.timestamp_now();
+        self.console.print(format!(
+            "{} {} # {}",
+            self.prefix, self.console_timestamp, msg
+        ));
+        let _ = self
+            .line_sender
+            .send(format!("{} {} # {}", self.prefix, self.log_timestamp, msg));
+    }
+
+    /// In hex display mode every read shows up as its own dump rows right
+    /// away, rather than waiting for a full row.
     pub fn push_bytes(&mut self, buffer: &[u8]) {
-        self.push_str(&String::from_utf8_lossy(buffer))
+        match self.display {
+            DisplayMode::Text => self.push_str(&String::from_utf8_lossy(buffer)),
+            DisplayMode::Hex => {
+                for row in buffer.chunks(HEX_ROW_LEN) {
+                    let line = hex_dump_row(self.hex_offset, row);
+                    self.hex_offset += row.len() as u64;
+                    send_split!(self, &line, &line);
+                }
+            }
+        }
+    }
+}
+
+/// Formats `00000010  7e 01 00  |~..|`, with the ASCII column aligned.
+fn hex_dump_row(offset: u64, row: &[u8]) -> String {
+    let hex: Vec<_> = row.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = row
+        .iter()
+        .map(|&b| match b {
+            0x20..=0x7e => b as char,
+            _ => '.',
+        })
+        .collect();
+    format!(
+        "{:08x}  {:<width$}  |{}|",
+        offset,
+        hex.join(" "),
+        ascii,
+        width = HEX_ROW_LEN * 3 - 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::AnsiMode, utils::console_output::PaneLine};
+    use std::sync::mpsc::{channel, Receiver};
+
+    /// A printer in `ansi` mode, with the receivers of its log and console
+    /// lines.
+    fn printer(ansi: AnsiMode) -> (LinePrinter, Receiver<String>, Receiver<PaneLine>) {
+        let (line_sender, log) = channel();
+        let (pane_sender, console) = channel();
+        let console_output = ConsoleOutput::Pane {
+            pane: 0,
+            sender: pane_sender,
+        };
+        let console_style = ConsoleStyle {
+            ansi,
+            ..Default::default()
+        };
+        let line_printer = LinePrinter::new(
+            "dev r".into(),
+            80,
+            line_sender,
+            console_output,
+            console_style,
+            WriteRoutes::new(),
+            Box::new([]),
+        );
+        (line_printer, log, console)
+    }
+
+    fn logged_texts(log: &Receiver<String>) -> Vec<String> {
+        log.try_iter()
+            .map(|line| parse_log_line(&line).unwrap().text.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn joins_an_escape_sequence_split_across_reads() {
+        let (mut line_printer, log, console) = printer(AnsiMode::Render);
+        line_printer.push_bytes(b"ab\x1b[3");
+        line_printer.push_bytes(b"1mred\x1b]0;ti");
+        line_printer.push_bytes(b"tle\x1b");
+        line_printer.push_bytes(b"\\done\n");
+        assert_eq!(logged_texts(&log), ["abreddone"]);
+        let line = console.try_recv().unwrap().line;
+        let rendered = "ab^[[31mred^[]0;title^[\\done";
+        assert!(line.ends_with(rendered), "{:?}", line);
+    }
+
+    #[test]
+    fn dumps_hex_rows_with_aligned_ascii() {
+        let full = hex_dump_row(0, b"0123456789abcdef");
+        assert_eq!(
+            full,
+            "00000000  30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|"
+        );
+        let short = hex_dump_row(0x10, &[0x7e, 0x01, 0x00]);
+        assert_eq!(short.find('|'), full.find('|'));
+        assert!(short.starts_with("00000010  7e 01 00 "));
+        assert!(short.ends_with("  |~..|"));
+    }
+
+    #[test]
+    fn dumps_hex_across_reads_with_running_offsets() {
+        let (mut line_printer, log, _console) = printer(AnsiMode::Pass);
+        line_printer.set_display(DisplayMode::Hex);
+        line_printer.push_bytes(&[b'A'; 20]);
+        line_printer.push_bytes(b"\r\n");
+        let rows = logged_texts(&log);
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].starts_with("00000000  41 41"));
+        assert!(rows[1].starts_with("00000010  41 41 41 41  "));
+        assert!(rows[2].starts_with("00000014  0d 0a  "));
+        assert!(rows[2].ends_with("|..|"));
     }
 }
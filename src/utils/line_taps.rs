@@ -0,0 +1,36 @@
+use crate::utils::waker::Waker;
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex,
+};
+
+struct LineTap {
+    sender: Sender<String>,
+    waker: Option<Waker>,
+}
+
+/// Copies of the lines the file logger writes, for threads that serve them
+/// to other processes. Dropping a receiver unsubscribes it.
+#[derive(Clone, Default)]
+pub struct LineTaps {
+    taps: Arc<Mutex<Vec<LineTap>>>,
+}
+
+impl LineTaps {
+    /// `waker` is woken after each line, for subscribers that poll fds.
+    pub fn subscribe(&self, waker: Option<Waker>) -> Receiver<String> {
+        let (sender, receiver) = channel();
+        self.taps.lock().unwrap().push(LineTap { sender, waker });
+        receiver
+    }
+
+    pub fn send(&self, line: &str) {
+        self.taps.lock().unwrap().retain(|tap| {
+            let alive = tap.sender.send(line.to_string()).is_ok();
+            if let (true, Some(waker)) = (alive, &tap.waker) {
+                waker.wake();
+            }
+            alive
+        });
+    }
+}
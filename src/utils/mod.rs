@@ -1,4 +1,15 @@
+pub mod capture;
+pub mod compression;
+pub mod console_output;
+pub mod line_printer;
+pub mod line_taps;
+pub mod rfc2217;
 pub mod ring_buf_queue;
+pub mod socket_buffer;
 pub mod sync_flag;
+pub mod telnet;
+pub mod terminal;
+pub mod transport;
 pub mod user_io;
-pub mod line_printer;
+pub mod waker;
+pub mod websocket;
@@ -0,0 +1,245 @@
+//! RFC 2217 client: a serial port on a remote terminal server (eg ser2net),
+//! reached over telnet with the com port option carrying the line settings.
+
+use crate::{
+    config::ProcessorInfo,
+    utils::{
+        telnet::{
+            escape_iac, negotiate, subnegotiate, TelnetCommand, TelnetParser, DO, DONT, IAC,
+            OPT_BINARY, OPT_SUPPRESS_GO_AHEAD, WILL, WONT,
+        },
+        transport::{Transport, PORT_TIMEOUT},
+        waker::{AsRawFd, RawFd},
+    },
+};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// Bounds connecting, and sending the initial port settings.
+pub const RFC2217_TIMEOUT: Duration = Duration::from_secs(5);
+
+const OPT_COM_PORT: u8 = 44;
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+/// The server answers a com port command with the command code plus this.
+const SERVER_REPLY_OFFSET: u8 = 100;
+
+/// The telnet options both ends use; anything else is refused.
+fn is_supported_option(option: u8) -> bool {
+    matches!(option, OPT_BINARY | OPT_SUPPRESS_GO_AHEAD | OPT_COM_PORT)
+}
+
+/// Com port commands that set the port up like `processor_info`.
+fn com_port_settings(processor_info: &ProcessorInfo) -> Vec<u8> {
+    let parity = match processor_info.parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    };
+    let data_bits = match processor_info.data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    };
+    let stop_bits = match processor_info.stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+    let flow_control = match processor_info.flow_control {
+        FlowControl::None => 1,
+        FlowControl::Software => 2,
+        FlowControl::Hardware => 3,
+    };
+    let mut baud_rate = vec![SET_BAUDRATE];
+    baud_rate.extend_from_slice(&processor_info.baud_rate.to_be_bytes());
+    [
+        baud_rate,
+        vec![SET_DATASIZE, data_bits],
+        vec![SET_PARITY, parity],
+        vec![SET_STOPSIZE, stop_bits],
+        vec![SET_CONTROL, flow_control],
+    ]
+    .iter()
+    .flat_map(|command| subnegotiate(OPT_COM_PORT, command))
+    .collect()
+}
+
+pub struct Rfc2217 {
+    stream: TcpStream,
+    /// Escaped bytes the server has not taken yet: telnet replies, or the
+    /// second half of an escaped `IAC` whose data byte was already counted.
+    pending: Vec<u8>,
+    parser: TelnetParser,
+    processor_name: String,
+    baud_rate: u32,
+    notices: Vec<String>,
+}
+
+impl Rfc2217 {
+    /// Connects to `addr`, `host:port`, and asks for `processor_info`'s line
+    /// settings. The server's answers arrive later and are only reported.
+    pub fn open(addr: &str, processor_info: &ProcessorInfo) -> io::Result<Self> {
+        let mut last_err = io::Error::new(ErrorKind::NotFound, "no address for host");
+        let mut stream = None;
+        for socket_addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&socket_addr, RFC2217_TIMEOUT) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        let mut stream = stream.ok_or(last_err)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(PORT_TIMEOUT))?;
+        stream.set_write_timeout(Some(RFC2217_TIMEOUT))?;
+
+        let mut setup = vec![];
+        for option in [OPT_BINARY, OPT_SUPPRESS_GO_AHEAD] {
+            setup.extend_from_slice(&negotiate(WILL, option));
+            setup.extend_from_slice(&negotiate(DO, option));
+        }
+        setup.extend_from_slice(&negotiate(WILL, OPT_COM_PORT));
+        setup.extend(com_port_settings(processor_info));
+        stream.write_all(&setup)?;
+        // from here on writes behave like a serial port's held off by flow control
+        stream.set_write_timeout(Some(PORT_TIMEOUT))?;
+
+        Ok(Self {
+            stream,
+            pending: vec![],
+            parser: TelnetParser::new(false),
+            processor_name: processor_info.processor_name.clone(),
+            baud_rate: processor_info.baud_rate,
+            notices: vec![],
+        })
+    }
+
+    /// Sends as much of `pending` as the server takes before the write
+    /// timeout.
+    fn send_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            let sent = self.stream.write(&self.pending)?;
+            self.pending.drain(..sent);
+        }
+        Ok(())
+    }
+
+    /// Queues replies in `pending`, sent by the next read or write.
+    fn handle_command(&mut self, command: TelnetCommand) {
+        match command {
+            TelnetCommand::Negotiate { verb: DO, option } if !is_supported_option(option) => {
+                self.pending.extend(negotiate(WONT, option))
+            }
+            TelnetCommand::Negotiate { verb: WILL, option } if !is_supported_option(option) => {
+                self.pending.extend(negotiate(DONT, option))
+            }
+            TelnetCommand::Negotiate {
+                verb: DONT,
+                option: OPT_COM_PORT,
+            } => {
+                self.notices.push(format!(
+                    "> [transport] {} server refused rfc2217, its port settings apply",
+                    self.processor_name
+                ));
+            }
+            TelnetCommand::Subnegotiation {
+                option: OPT_COM_PORT,
+                bytes,
+            } => {
+                if let [code, baud @ ..] = &bytes[..] {
+                    let baud_rate = baud.try_into().map(u32::from_be_bytes);
+                    if *code == SET_BAUDRATE + SERVER_REPLY_OFFSET
+                        && baud_rate.is_ok_and(|baud_rate| baud_rate != self.baud_rate)
+                    {
+                        self.notices.push(format!(
+                            "> [transport] {} server runs at {} baud",
+                            self.processor_name,
+                            baud_rate.unwrap()
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Read for Rfc2217 {
+    /// A read that only carried telnet commands fails with `WouldBlock`,
+    /// and a closed connection with `UnexpectedEof`, so the console
+    /// reconnects.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = vec![0u8; buf.len()];
+        let count = self.stream.read(&mut raw)?;
+        if count == 0 {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "rfc2217 server closed the connection",
+            ));
+        }
+        // unescaping only ever shrinks the data, so it fits in `buf`
+        let mut data = Vec::with_capacity(count);
+        for command in self.parser.parse(&raw[..count], &mut data) {
+            self.handle_command(command);
+        }
+        // replies the server does not take now go out with the next write
+        match self.send_pending() {
+            Err(e) if !matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(e)
+            }
+            _ => {}
+        }
+        if data.is_empty() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Write for Rfc2217 {
+    /// Returns how many bytes of `buf` the server took before the write
+    /// timeout. A byte counts once the first byte of its escaped form is
+    /// sent; the rest of an escaped `IAC` goes out ahead of the next write.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send_pending()?;
+        let mut escaped = Vec::with_capacity(buf.len());
+        escape_iac(buf, &mut escaped);
+        let sent = self.stream.write(&escaped)?;
+        let mut consumed = 0;
+        let mut escaped_end = 0;
+        while escaped_end < sent {
+            escaped_end += if buf[consumed] == IAC { 2 } else { 1 };
+            consumed += 1;
+        }
+        self.pending.extend_from_slice(&escaped[sent..escaped_end]);
+        Ok(consumed)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_pending()?;
+        self.stream.flush()
+    }
+}
+
+impl AsRawFd for Rfc2217 {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+impl Transport for Rfc2217 {
+    fn take_notices(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.notices)
+    }
+}
@@ -1,5 +1,14 @@
+use crate::utils::waker::Waker;
 use ringbuf::{Consumer, Producer, RingBuffer};
-use std::{borrow::Cow, collections::VecDeque};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Whatever did not fit in the ring buffer, oldest first. Shared so the
+/// consumer can take it without waiting for the producer's next push.
+type Overflow<T> = Arc<Mutex<VecDeque<Box<[T]>>>>;
 
 pub fn new_ring_buf_q<const BUF_SIZE: usize, T>(
 ) -> (RingBufQProducer<T>, RingBufQConsumer<BUF_SIZE, T>)
@@ -7,63 +16,63 @@ where
     T: Default + Copy,
 {
     let (prod, cons) = RingBuffer::new(BUF_SIZE).split();
+    let q: Overflow<T> = Default::default();
 
     let prod = RingBufQProducer {
-        q: VecDeque::new(),
+        q: q.clone(),
         prod,
+        waker: None,
     };
     let cons = RingBufQConsumer {
         buffer: [T::default(); BUF_SIZE],
-        q: VecDeque::new(),
+        q,
         cons,
     };
     (prod, cons)
 }
 
 pub struct RingBufQProducer<T> {
-    q: VecDeque<Box<[T]>>,
+    q: Overflow<T>,
     prod: Producer<T>,
+    waker: Option<Waker>,
 }
 
 impl<T> RingBufQProducer<T>
 where
     T: Copy,
 {
-    pub fn flush(&mut self) {
-        while let Some(first) = self.q.pop_front() {
-            let ret = self.prod.push_slice(&first);
-            if ret == 0 {
-                self.q.push_front(first);
-                break;
-            } else if ret < first.len() {
-                self.q.push_front(first[ret..].into());
-                break;
-            }
-        }
+    /// Wakes the consumer's thread whenever elements are pushed.
+    pub fn set_waker(&mut self, waker: Waker) {
+        self.waker = Some(waker);
     }
 
     pub fn push<E>(&mut self, elems: E)
     where
         E: AsRef<[T]> + Into<Box<[T]>>,
     {
-        self.flush();
-        if elems.as_ref().len() == 0 {
+        if elems.as_ref().is_empty() {
             return;
         }
-        if self.q.is_empty() {
+        // the ring only takes more once the overflow is drained, to keep the order
+        let mut q = self.q.lock().unwrap();
+        if q.is_empty() {
             let ret = self.prod.push_slice(elems.as_ref());
             if ret < elems.as_ref().len() {
-                self.q.push_back(elems.as_ref()[ret..].into())
+                q.push_back(elems.as_ref()[ret..].into())
             }
         } else {
-            self.q.push_back(elems.into())
+            q.push_back(elems.into())
+        }
+        drop(q);
+        if let Some(waker) = &self.waker {
+            waker.wake();
         }
     }
 }
 
 pub struct RingBufQConsumer<const BUF_SIZE: usize, T> {
     buffer: [T; BUF_SIZE],
-    q: VecDeque<Box<[T]>>,
+    q: Overflow<T>,
     cons: Consumer<T>,
 }
 
@@ -71,18 +80,16 @@ impl<const BUF_SIZE: usize, T> RingBufQConsumer<BUF_SIZE, T>
 where
     T: Copy,
 {
+    /// Takes the oldest queued elements, empty once everything is taken.
     pub fn pop(&mut self) -> Cow<'_, [T]> {
         let elems = &mut self.buffer;
         let ret = self.cons.pop_slice(elems);
-
-        match self.q.pop_front() {
-            Some(first) => {
-                if ret > 0 {
-                    self.q.push_back(elems[..ret].into());
-                }
-                Cow::Owned(first.to_vec())
-            }
-            None => Cow::Borrowed(&elems[..ret]),
+        if ret > 0 {
+            return Cow::Borrowed(&elems[..ret]);
+        }
+        match self.q.lock().unwrap().pop_front() {
+            Some(first) => Cow::Owned(first.into_vec()),
+            None => Cow::Borrowed(&[]),
         }
     }
 }
@@ -0,0 +1,80 @@
+//! The nonblocking socket plumbing shared by the bridge, control and HTTP
+//! clients.
+
+use crate::utils::waker::{AsRawFd, RawFd};
+use std::io::{ErrorKind, Read, Write};
+
+/// Output a client may fall behind by, or unparsed input it may send ahead,
+/// before it is disconnected.
+pub const MAX_CLIENT_BACKLOG: usize = 1 << 20;
+
+/// A nonblocking stream with the output it would not take yet. Any error or
+/// a client too far behind marks it `closed`, for the task to drop.
+pub struct SocketBuffer<S: Read + Write> {
+    stream: S,
+    backlog: Vec<u8>,
+    pub closed: bool,
+}
+
+impl<S: Read + Write> SocketBuffer<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            backlog: vec![],
+            closed: false,
+        }
+    }
+
+    /// Queues `bytes` for the next `flush`.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.backlog.extend_from_slice(bytes);
+        if self.backlog.len() > MAX_CLIENT_BACKLOG {
+            self.closed = true;
+        }
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        self.backlog.is_empty()
+    }
+
+    /// Writes as much of the backlog as the socket takes without blocking.
+    pub fn flush(&mut self) {
+        while !self.backlog.is_empty() && !self.closed {
+            match self.stream.write(&self.backlog) {
+                Ok(0) => self.closed = true,
+                Ok(count) => {
+                    self.backlog.drain(..count);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.closed = true,
+            }
+        }
+    }
+
+    /// Appends everything readable without blocking to `input`.
+    pub fn read_available(&mut self, input: &mut Vec<u8>) {
+        let mut read_buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut read_buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(count) => input.extend_from_slice(&read_buf[..count]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Read + Write + AsRawFd> AsRawFd for SocketBuffer<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
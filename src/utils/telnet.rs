@@ -0,0 +1,135 @@
+//! Telnet (RFC 854) framing: commands are introduced by `IAC`, and a data
+//! byte of 255 is sent as `IAC IAC`.
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+/// Starts a subnegotiation, ended by `IAC SE`.
+pub const SB: u8 = 250;
+pub const SE: u8 = 240;
+
+pub const OPT_BINARY: u8 = 0;
+pub const OPT_ECHO: u8 = 1;
+pub const OPT_SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// A command removed from the data stream.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TelnetCommand {
+    /// `DO`, `DONT`, `WILL` or `WONT` an option.
+    Negotiate {
+        verb: u8,
+        option: u8,
+    },
+    Subnegotiation {
+        option: u8,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum ParseState {
+    Data,
+    Cr,
+    Iac,
+    Negotiate(u8),
+    SubOption,
+    Sub,
+    SubIac,
+}
+
+/// Splits a telnet stream into data and commands. Commands and data may be
+/// split across reads.
+pub struct TelnetParser {
+    state: ParseState,
+    /// Drop the `NUL` a network virtual terminal sends after a bare `CR`.
+    strip_cr_nul: bool,
+    sub_option: u8,
+    sub_bytes: Vec<u8>,
+}
+
+impl TelnetParser {
+    pub fn new(strip_cr_nul: bool) -> Self {
+        Self {
+            state: ParseState::Data,
+            strip_cr_nul,
+            sub_option: 0,
+            sub_bytes: vec![],
+        }
+    }
+
+    /// Appends the data in `input` to `data` and returns the commands.
+    pub fn parse(&mut self, input: &[u8], data: &mut Vec<u8>) -> Vec<TelnetCommand> {
+        let mut commands = vec![];
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (ParseState::Data | ParseState::Cr, IAC) => ParseState::Iac,
+                (ParseState::Cr, 0) => ParseState::Data,
+                (ParseState::Data | ParseState::Cr, _) => {
+                    data.push(byte);
+                    match byte == b'\r' && self.strip_cr_nul {
+                        true => ParseState::Cr,
+                        false => ParseState::Data,
+                    }
+                }
+                (ParseState::Iac, IAC) => {
+                    data.push(IAC);
+                    ParseState::Data
+                }
+                (ParseState::Iac, DO | DONT | WILL | WONT) => ParseState::Negotiate(byte),
+                (ParseState::Iac, SB) => ParseState::SubOption,
+                // NOP, go ahead, break and the like carry nothing to act on
+                (ParseState::Iac, _) => ParseState::Data,
+                (ParseState::Negotiate(verb), option) => {
+                    commands.push(TelnetCommand::Negotiate { verb, option });
+                    ParseState::Data
+                }
+                (ParseState::SubOption, option) => {
+                    self.sub_option = option;
+                    self.sub_bytes.clear();
+                    ParseState::Sub
+                }
+                (ParseState::Sub, IAC) => ParseState::SubIac,
+                (ParseState::Sub, _) => {
+                    self.sub_bytes.push(byte);
+                    ParseState::Sub
+                }
+                (ParseState::SubIac, SE) => {
+                    commands.push(TelnetCommand::Subnegotiation {
+                        option: self.sub_option,
+                        bytes: std::mem::take(&mut self.sub_bytes),
+                    });
+                    ParseState::Data
+                }
+                (ParseState::SubIac, _) => {
+                    self.sub_bytes.push(byte);
+                    ParseState::Sub
+                }
+            };
+        }
+        commands
+    }
+}
+
+/// Appends `data` to `out` with every 255 doubled.
+pub fn escape_iac(data: &[u8], out: &mut Vec<u8>) {
+    for &byte in data {
+        if byte == IAC {
+            out.push(IAC);
+        }
+        out.push(byte);
+    }
+}
+
+pub fn negotiate(verb: u8, option: u8) -> [u8; 3] {
+    [IAC, verb, option]
+}
+
+/// `IAC SB <option> <bytes> IAC SE`, with any 255 in `bytes` doubled.
+pub fn subnegotiate(option: u8, bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![IAC, SB, option];
+    escape_iac(bytes, &mut out);
+    out.extend_from_slice(&[IAC, SE]);
+    out
+}
@@ -0,0 +1,401 @@
+#[cfg(unix)]
+use std::{
+    fs::File,
+    io::Read,
+    mem::{ManuallyDrop, MaybeUninit},
+    os::unix::io::{FromRawFd, RawFd},
+};
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+pub const ENTER_ALT_SCREEN: &str = "\x1b[?1049h";
+pub const LEAVE_ALT_SCREEN: &str = "\x1b[?1049l";
+pub const CLEAR_SCREEN: &str = "\x1b[2J";
+pub const CLEAR_TO_EOL: &str = "\x1b[K";
+pub const REVERSE_VIDEO: &str = "\x1b[7m";
+pub const RESET_STYLE: &str = "\x1b[0m";
+
+#[cfg(unix)]
+const STDIN_FD: RawFd = 0;
+#[cfg(unix)]
+const STDOUT_FD: RawFd = 1;
+
+/// Puts stdin in raw mode until dropped. Output post-processing stays on so
+/// stray `println!`s from other threads still start at column 0. Only unix
+/// terminals are supported.
+pub struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    #[cfg(not(unix))]
+    pub fn enable() -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(unix)]
+    pub fn enable() -> io::Result<Self> {
+        let mut original = MaybeUninit::uninit();
+        if unsafe { libc::tcgetattr(STDIN_FD, original.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = unsafe { original.assume_init() };
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        raw.c_oflag |= libc::OPOST | libc::ONLCR;
+        if unsafe { libc::tcsetattr(STDIN_FD, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(STDIN_FD, libc::TCSANOW, &self.original)
+        };
+    }
+}
+
+/// `(rows, cols)` of the terminal on stdout.
+#[cfg(not(unix))]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    None
+}
+
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut size = MaybeUninit::<libc::winsize>::uninit();
+    if unsafe { libc::ioctl(STDOUT_FD, libc::TIOCGWINSZ, size.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let size = unsafe { size.assume_init() };
+    match (size.ws_row, size.ws_col) {
+        (0, _) | (_, 0) => None,
+        (rows, cols) => Some((rows.into(), cols.into())),
+    }
+}
+
+static SIGINT_CAUGHT: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    SIGINT_CAUGHT.store(true, Ordering::Relaxed);
+}
+
+/// Makes SIGINT set the flag read by [`sigint_caught`] instead of killing the
+/// process, so a session without a terminal still shuts down cleanly. Other
+/// platforms keep the default of ending the process.
+#[cfg(not(unix))]
+pub fn catch_sigint() {}
+
+#[cfg(unix)]
+pub fn catch_sigint() {
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+pub fn sigint_caught() -> bool {
+    SIGINT_CAUGHT.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Bright(u8),
+}
+
+pub const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Color {
+    /// Parses `red`, `bright_red`, etc.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (bright, base) = match name.strip_prefix("bright_") {
+            Some(base) => (true, base),
+            None => (false, name),
+        };
+        let idx = COLOR_NAMES.iter().position(|n| *n == base)? as u8;
+        Some(match (bright, idx) {
+            (true, idx) => Color::Bright(idx),
+            (false, 0) => Color::Black,
+            (false, 1) => Color::Red,
+            (false, 2) => Color::Green,
+            (false, 3) => Color::Yellow,
+            (false, 4) => Color::Blue,
+            (false, 5) => Color::Magenta,
+            (false, 6) => Color::Cyan,
+            (false, _) => Color::White,
+        })
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Color::Bright(idx) => format!("bright_{}", COLOR_NAMES[idx as usize % 8]),
+            color => COLOR_NAMES[color.index() as usize].to_string(),
+        }
+    }
+
+    fn index(self) -> u8 {
+        match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::Bright(idx) => idx % 8,
+        }
+    }
+
+    /// SGR sequence selecting this foreground color.
+    pub fn fg(self) -> String {
+        let base = if let Color::Bright(_) = self { 90 } else { 30 };
+        format!("\x1b[{}m", base + self.index())
+    }
+}
+
+/// Byte length of the escape sequence `seq` starts with, or `None` while it
+/// is still incomplete. Handles CSI (`ESC [`), OSC (`ESC ]`, ended by BEL or
+/// `ESC \`) and two char `ESC x` sequences.
+pub fn escape_len(seq: &str) -> Option<usize> {
+    let mut chars = seq.char_indices().skip(1);
+    match chars.next()? {
+        (_, '[') => chars
+            .find(|(_, ch)| ('\x40'..='\x7e').contains(ch))
+            .map(|(i, _)| i + 1),
+        (_, ']') => {
+            let mut last = ' ';
+            for (i, ch) in chars {
+                if ch == '\x07' || (last == '\x1b' && ch == '\\') {
+                    return Some(i + 1);
+                }
+                last = ch;
+            }
+            None
+        }
+        (i, ch) => Some(i + ch.len_utf8()),
+    }
+}
+
+/// `line` with every escape sequence but SGR (`ESC [ ... m`, colors and
+/// text styles) removed, so cursor moves, clears and scroll regions can't
+/// mess up the screen.
+pub fn keep_sgr_only(line: &str) -> String {
+    let mut kept = String::new();
+    let mut idx = 0;
+    while let Some(ch) = line[idx..].chars().next() {
+        if ch != '\x1b' {
+            kept.push(ch);
+            idx += ch.len_utf8();
+            continue;
+        }
+        let len = escape_len(&line[idx..]).unwrap_or(line.len() - idx);
+        let seq = &line[idx..idx + len];
+        if seq.starts_with("\x1b[") && seq.ends_with('m') {
+            kept.push_str(seq);
+        }
+        idx += len;
+    }
+    kept
+}
+
+/// Truncates `line` to `cols` visible chars; escape sequences are kept but
+/// take no width.
+pub fn truncate_visible(line: &str, cols: usize) -> String {
+    let mut truncated = String::new();
+    let mut width = 0;
+    let mut idx = 0;
+    while let Some(ch) = line[idx..].chars().next() {
+        let len = match ch {
+            '\x1b' => escape_len(&line[idx..]).unwrap_or(line.len() - idx),
+            _ => ch.len_utf8(),
+        };
+        if ch == '\x1b' {
+            truncated.push_str(&line[idx..idx + len]);
+        } else if width < cols {
+            truncated.push(ch);
+            width += 1;
+        }
+        idx += len;
+    }
+    truncated
+}
+
+/// Byte index in `line` where its `visible_idx`'th visible byte sits, with
+/// escape sequences skipped.
+pub fn visible_to_raw_idx(line: &str, visible_idx: usize) -> usize {
+    let mut visible = 0;
+    let mut idx = 0;
+    while let Some(ch) = line[idx..].chars().next() {
+        if ch == '\x1b' {
+            idx += escape_len(&line[idx..]).unwrap_or(line.len() - idx);
+            continue;
+        }
+        if visible >= visible_idx {
+            break;
+        }
+        visible += ch.len_utf8();
+        idx += ch.len_utf8();
+    }
+    idx
+}
+
+pub fn move_to(row: usize, col: usize) -> String {
+    format!("\x1b[{};{}H", row + 1, col + 1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Esc,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+/// Reads whatever is pending on stdin and decodes it into keys. Bypasses
+/// `io::stdin()`'s buffer so polling the fd never misses buffered input.
+#[cfg(not(unix))]
+pub fn read_keys() -> io::Result<Vec<Key>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(unix)]
+pub fn read_keys() -> io::Result<Vec<Key>> {
+    let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(STDIN_FD) });
+    let mut buf = [0u8; 256];
+    let count = stdin.read(&mut buf)?;
+    Ok(decode_keys(&buf[..count]))
+}
+
+pub fn decode_keys(mut bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    while let Some(&first) = bytes.first() {
+        let (key, len) = match first {
+            0x1b => decode_escape(bytes),
+            b'\r' | b'\n' => (Some(Key::Enter), 1),
+            b'\t' => (Some(Key::Tab), 1),
+            0x7f | 0x08 => (Some(Key::Backspace), 1),
+            0x01..=0x1a => (Some(Key::Ctrl((b'a' + first - 1) as char)), 1),
+            0x00..=0x1f => (None, 1),
+            _ => decode_utf8(bytes),
+        };
+        keys.extend(key);
+        bytes = &bytes[len..];
+    }
+    keys
+}
+
+fn decode_escape(bytes: &[u8]) -> (Option<Key>, usize) {
+    match bytes.get(1) {
+        None => (Some(Key::Esc), 1),
+        Some(b'[') | Some(b'O') => {
+            let Some(end) = bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b)) else {
+                return (None, bytes.len());
+            };
+            let params = &bytes[2..2 + end];
+            let key = match (params, bytes[2 + end]) {
+                (_, b'A') => Some(Key::Up),
+                (_, b'B') => Some(Key::Down),
+                (_, b'C') => Some(Key::Right),
+                (_, b'D') => Some(Key::Left),
+                (_, b'H') | (b"1" | b"7", b'~') => Some(Key::Home),
+                (_, b'F') | (b"4" | b"8", b'~') => Some(Key::End),
+                (b"3", b'~') => Some(Key::Delete),
+                (b"5", b'~') => Some(Key::PageUp),
+                (b"6", b'~') => Some(Key::PageDown),
+                _ => None,
+            };
+            (key, 3 + end)
+        }
+        Some(_) => (Some(Key::Esc), 1),
+    }
+}
+
+fn decode_utf8(bytes: &[u8]) -> (Option<Key>, usize) {
+    let len = match bytes[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let len = len.min(bytes.len());
+    match std::str::from_utf8(&bytes[..len]) {
+        Ok(s) => (s.chars().next().map(Key::Char), len),
+        Err(_) => (None, len),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_escape_sequences() {
+        assert_eq!(escape_len("\x1b[31mred"), Some(5));
+        assert_eq!(escape_len("\x1b[1;31;42mred"), Some(10));
+        assert_eq!(escape_len("\x1bcreset"), Some(2));
+        assert_eq!(escape_len("\x1b]0;title\x07rest"), Some(10));
+        assert_eq!(escape_len("\x1b]8;;http://a\x1b\\link"), Some(15));
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_split_sequence() {
+        let partials = ["\x1b", "\x1b[", "\x1b[31", "\x1b]0;t", "\x1b]0;t\x1b"];
+        for partial in partials {
+            assert_eq!(escape_len(partial), None, "{:?}", partial);
+        }
+    }
+
+    #[test]
+    fn truncates_visible_chars_only() {
+        let line = "\x1b[31mabcdef\x1b[0m";
+        assert_eq!(truncate_visible(line, 3), "\x1b[31mabc\x1b[0m");
+        assert_eq!(truncate_visible(line, 10), line);
+        assert_eq!(truncate_visible("h\u{e9}llo", 2), "h\u{e9}");
+        // an unterminated sequence is kept whole rather than counted
+        assert_eq!(truncate_visible("ab\x1b[3", 1), "a\x1b[3");
+    }
+
+    #[test]
+    fn maps_visible_to_raw_indices() {
+        let line = "\x1b[31mab\x1b]0;t\x1b\\cd";
+        assert_eq!(visible_to_raw_idx(line, 0), 5);
+        assert_eq!(visible_to_raw_idx(line, 1), 6);
+        assert_eq!(visible_to_raw_idx(line, 2), 14);
+        assert_eq!(visible_to_raw_idx(line, 4), line.len());
+        assert_eq!(visible_to_raw_idx("plain", 3), 3);
+    }
+
+    #[test]
+    fn keeps_only_sgr_sequences() {
+        let line = "\x1b[2J\x1b[H\x1b[1;32mok\x1b[0m\x1b]0;t\x07\x1b[?25l";
+        assert_eq!(keep_sgr_only(line), "\x1b[1;32mok\x1b[0m");
+    }
+}
@@ -0,0 +1,236 @@
+#[cfg(unix)]
+use crate::utils::waker::RawFd;
+use crate::{
+    config::ProcessorInfo,
+    utils::{rfc2217::Rfc2217, waker::AsRawFd},
+};
+use serialport::SerialPortBuilder;
+#[cfg(unix)]
+use serialport::{SerialPort, TTYPort};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+use std::{collections::VecDeque, io::ErrorKind};
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// Port name of a loopback transport, eg `loop://` or `loop://a`.
+pub const LOOPBACK_SCHEME: &str = "loop://";
+/// Port name of a new pseudo-terminal, eg `pty://`.
+pub const PTY_SCHEME: &str = "pty://";
+/// Port name of a port on an RFC 2217 server, eg `rfc2217://bench1:4001`.
+pub const RFC2217_SCHEME: &str = "rfc2217://";
+/// Read and write timeout of opened ports; reads only happen once the fd
+/// polls readable, so this only bounds writes held off by flow control.
+pub const PORT_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// A byte stream to a processor: a serial port, or a stand-in for one.
+/// `serial_console_task` polls `as_raw_fd` for readability before reading.
+pub trait Transport: Read + Write + AsRawFd + Send {
+    /// Messages about the link since the last call, eg a server's answer to
+    /// the line settings, for the console to show as markers.
+    fn take_notices(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// What `serialport` opens on this platform; only unix ports can be polled.
+#[cfg(unix)]
+pub type NativePort = TTYPort;
+#[cfg(not(unix))]
+pub type NativePort = Box<dyn serialport::SerialPort>;
+
+impl Transport for NativePort {}
+
+/// Reads back whatever was written, like a jumper between a port's TX and
+/// RX pins.
+pub struct Loopback {
+    #[cfg(unix)]
+    tx: UnixStream,
+    #[cfg(unix)]
+    rx: UnixStream,
+    /// Without a socket pair to poll, the looped bytes just wait here.
+    #[cfg(not(unix))]
+    looped: VecDeque<u8>,
+}
+
+impl Loopback {
+    #[cfg(unix)]
+    pub fn new() -> io::Result<Self> {
+        let (tx, rx) = UnixStream::pair()?;
+        // the only reader is the writer's own thread, so never block on a full buffer
+        tx.set_nonblocking(true)?;
+        Ok(Self { tx, rx })
+    }
+
+    #[cfg(not(unix))]
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            looped: VecDeque::new(),
+        })
+    }
+}
+
+impl Read for Loopback {
+    #[cfg(unix)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rx.read(buf)
+    }
+
+    #[cfg(not(unix))]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.looped.read(buf)? {
+            0 => Err(ErrorKind::WouldBlock.into()),
+            count => Ok(count),
+        }
+    }
+}
+
+impl Write for Loopback {
+    #[cfg(unix)]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.write(buf)
+    }
+
+    #[cfg(not(unix))]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.looped.extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for Loopback {
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> RawFd {
+        self.rx.as_raw_fd()
+    }
+}
+
+impl Transport for Loopback {}
+
+/// The master side of a new pseudo-terminal. A simulator or test opens the
+/// slave path as if it were the processor's serial port.
+#[cfg(unix)]
+pub struct Pty {
+    master: TTYPort,
+    /// Held open so the master never sees a hang up between slave sessions.
+    slave: TTYPort,
+    notices: Vec<String>,
+}
+
+#[cfg(unix)]
+impl Pty {
+    pub fn new() -> io::Result<Self> {
+        let (mut master, slave) = TTYPort::pair()?;
+        master.set_timeout(PORT_TIMEOUT)?;
+        Ok(Self {
+            master,
+            slave,
+            notices: vec![],
+        })
+    }
+
+    pub fn slave_name(&self) -> Option<String> {
+        self.slave.name()
+    }
+}
+
+#[cfg(unix)]
+impl Read for Pty {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.master.read(buf)
+    }
+}
+
+#[cfg(unix)]
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Pty {
+    fn as_raw_fd(&self) -> RawFd {
+        self.master.as_raw_fd()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for Pty {
+    fn take_notices(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.notices)
+    }
+}
+
+#[cfg(unix)]
+fn open_pty(processor_info: &ProcessorInfo) -> io::Result<Box<dyn Transport>> {
+    let mut pty = Pty::new()?;
+    pty.notices.push(format!(
+        "> [transport] {} is on pty {}",
+        processor_info.processor_name,
+        pty.slave_name().unwrap_or_default()
+    ));
+    Ok(Box::new(pty))
+}
+
+#[cfg(not(unix))]
+fn open_pty(_: &ProcessorInfo) -> io::Result<Box<dyn Transport>> {
+    Err(io::Error::new(ErrorKind::Unsupported, "pty:// needs unix"))
+}
+
+/// Ports that are never enumerated but can still be opened: `loop://`,
+/// `pty://`, `rfc2217://` and existing device paths such as a PTY slave.
+pub fn is_unlisted_port(port_name: &str) -> bool {
+    port_name.starts_with(LOOPBACK_SCHEME)
+        || port_name.starts_with(PTY_SCHEME)
+        || port_name.starts_with(RFC2217_SCHEME)
+        || Path::new(port_name).exists()
+}
+
+pub fn open_serial_port(processor_info: &ProcessorInfo) -> serialport::Result<NativePort> {
+    let path = processor_info.port_name.clone();
+    let baud_rate = processor_info.baud_rate;
+    let builder = serialport::new(path, baud_rate)
+        .data_bits(processor_info.data_bits)
+        .parity(processor_info.parity)
+        .stop_bits(processor_info.stop_bits)
+        .flow_control(processor_info.flow_control)
+        .timeout(PORT_TIMEOUT);
+    open_native(builder)
+}
+
+#[cfg(unix)]
+fn open_native(builder: SerialPortBuilder) -> serialport::Result<NativePort> {
+    builder.open_native()
+}
+
+#[cfg(not(unix))]
+fn open_native(builder: SerialPortBuilder) -> serialport::Result<NativePort> {
+    builder.open()
+}
+
+/// Opens the transport `processor_info.port_name` names.
+pub fn open_transport(processor_info: &ProcessorInfo) -> io::Result<Box<dyn Transport>> {
+    let port_name = &processor_info.port_name;
+    if port_name.starts_with(LOOPBACK_SCHEME) {
+        Ok(Box::new(Loopback::new()?))
+    } else if port_name.starts_with(PTY_SCHEME) {
+        open_pty(processor_info)
+    } else if let Some(addr) = port_name.strip_prefix(RFC2217_SCHEME) {
+        Ok(Box::new(Rfc2217::open(addr, processor_info)?))
+    } else {
+        Ok(Box::new(open_serial_port(processor_info)?))
+    }
+}
@@ -17,12 +17,34 @@ impl<T, E: Any + Send> BoxErr<T> for Result<T, E> {
     }
 }
 
+/// Best effort message for the error types this crate boxes.
+pub fn box_err_msg(e: &BoxError) -> String {
+    if let Some(e) = e.downcast_ref::<RaisedError>() {
+        e.to_string()
+    } else if let Some(e) = e.downcast_ref::<std::io::Error>() {
+        e.to_string()
+    } else if let Some(e) = e.downcast_ref::<serialport::Error>() {
+        e.to_string()
+    } else if let Some(e) = e.downcast_ref::<serde_json::Error>() {
+        e.to_string()
+    } else if let Some(e) = e.downcast_ref::<regex::Error>() {
+        e.to_string()
+    } else if let Some(e) = e.downcast_ref::<String>() {
+        e.clone()
+    } else if let Some(e) = e.downcast_ref::<&str>() {
+        e.to_string()
+    } else {
+        format!("{:?}", e)
+    }
+}
+
 #[derive(Debug)]
 pub struct RaisedError {
     msg: String,
 }
 
 impl RaisedError {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(msg: &str) -> BoxError {
         Box::new(Self {
             msg: String::from(msg),
@@ -71,7 +93,7 @@ where
     let mut editor = Editor::<()>::new();
     match editor.readline(&format!("{}: ", msg)) {
         Ok(user_entry) => {
-            if user_entry.len() == 0 {
+            if user_entry.is_empty() {
                 ReadAndParseUserEntryRes::EmptyEntry
             } else {
                 match user_entry.parse::<T>() {
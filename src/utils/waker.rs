@@ -0,0 +1,124 @@
+//! Blocking until an fd is readable, and waking a thread blocked that way.
+//! Without `poll(2)` the wait is a short sleep that reports every fd ready,
+//! so callers simply retry their nonblocking or timed out reads.
+
+use std::{io, time::Duration};
+#[cfg(unix)]
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    sync::Arc,
+};
+
+#[cfg(unix)]
+pub use std::os::unix::io::{AsRawFd, RawFd};
+
+#[cfg(not(unix))]
+pub type RawFd = i32;
+
+/// Stand-in for the unix trait so pollers build everywhere; the fd is
+/// never looked at.
+#[cfg(not(unix))]
+pub trait AsRawFd {
+    fn as_raw_fd(&self) -> RawFd {
+        -1
+    }
+}
+
+#[cfg(not(unix))]
+impl AsRawFd for std::net::TcpStream {}
+#[cfg(not(unix))]
+impl AsRawFd for std::net::TcpListener {}
+#[cfg(not(unix))]
+impl AsRawFd for Box<dyn serialport::SerialPort> {}
+
+/// How long a wait lasts without `poll`, which bounds read and wake latency.
+#[cfg(not(unix))]
+const FALLBACK_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+/// Wakes a thread blocked in [`wait_readable`] on the paired [`WakeReceiver`].
+#[derive(Clone)]
+pub struct Waker {
+    #[cfg(unix)]
+    stream: Arc<UnixStream>,
+}
+
+pub struct WakeReceiver {
+    #[cfg(unix)]
+    stream: UnixStream,
+}
+
+#[cfg(unix)]
+pub fn new_waker() -> io::Result<(Waker, WakeReceiver)> {
+    let (sender, receiver) = UnixStream::pair()?;
+    sender.set_nonblocking(true)?;
+    receiver.set_nonblocking(true)?;
+    Ok((
+        Waker {
+            stream: Arc::new(sender),
+        },
+        WakeReceiver { stream: receiver },
+    ))
+}
+
+/// Waits never outlast [`FALLBACK_POLL_PERIOD`], so there is nothing to wake.
+#[cfg(not(unix))]
+pub fn new_waker() -> io::Result<(Waker, WakeReceiver)> {
+    Ok((Waker {}, WakeReceiver {}))
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        // a full socket buffer already guarantees a pending wake up
+        #[cfg(unix)]
+        let _ = (&*self.stream).write(&[1]);
+    }
+}
+
+impl WakeReceiver {
+    pub fn drain(&self) {
+        #[cfg(unix)]
+        {
+            let mut buf = [0u8; 64];
+            while let Ok(1..) = (&self.stream).read(&mut buf) {}
+        }
+    }
+}
+
+impl AsRawFd for WakeReceiver {
+    #[cfg(unix)]
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+/// Blocks until one of `fds` is readable (or hung up) or `timeout` passes.
+/// Returns the readiness of each fd in order.
+#[cfg(unix)]
+pub fn wait_readable(fds: &[RawFd], timeout: Duration) -> io::Result<Vec<bool>> {
+    let mut poll_fds: Vec<_> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    let ret = unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as _, timeout) };
+    if ret < 0 {
+        let e = io::Error::last_os_error();
+        return match e.kind() {
+            io::ErrorKind::Interrupted => Ok(vec![false; fds.len()]),
+            _ => Err(e),
+        };
+    }
+    let ready = libc::POLLIN | libc::POLLHUP | libc::POLLERR | libc::POLLNVAL;
+    Ok(poll_fds.iter().map(|p| p.revents & ready != 0).collect())
+}
+
+#[cfg(not(unix))]
+pub fn wait_readable(fds: &[RawFd], timeout: Duration) -> io::Result<Vec<bool>> {
+    std::thread::sleep(timeout.min(FALLBACK_POLL_PERIOD));
+    Ok(vec![true; fds.len()])
+}
@@ -0,0 +1,134 @@
+//! The server side of WebSocket (RFC 6455) framing and handshake.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha1::{Digest, Sha1};
+
+/// Appended to the client's key before hashing it into the accept key.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xa;
+
+/// The `Sec-WebSocket-Accept` answer to a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.trim());
+    sha1.update(HANDSHAKE_GUID);
+    STANDARD.encode(sha1.finalize())
+}
+
+/// A single unfragmented, unmasked frame, as servers send them.
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Takes the first complete frame off `input`, unmasking its payload.
+/// Returns `None` while the frame is still arriving.
+pub fn decode_frame(input: &mut Vec<u8>) -> Option<Frame> {
+    let opcode = input.first()? & 0x0f;
+    let masked = input.get(1)? & 0x80 != 0;
+    let (len, mut offset) = match input[1] & 0x7f {
+        126 => (
+            u16::from_be_bytes(input.get(2..4)?.try_into().ok()?) as usize,
+            4,
+        ),
+        127 => (
+            u64::from_be_bytes(input.get(2..10)?.try_into().ok()?) as usize,
+            10,
+        ),
+        len => (len as usize, 2),
+    };
+    let mut mask = [0u8; 4];
+    if masked {
+        mask.copy_from_slice(input.get(offset..offset + 4)?);
+        offset += 4;
+    }
+    let mut payload = input.get(offset..offset.checked_add(len)?)?.to_vec();
+    if masked {
+        for (idx, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[idx % 4];
+        }
+    }
+    input.drain(..offset + len);
+    Some(Frame { opcode, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn decodes_masked_frame() {
+        // the masked "Hello" of RFC 6455 section 5.7, followed by another frame
+        let mut input = vec![
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58, 0x89, 0x00,
+        ];
+        let frame = decode_frame(&mut input).unwrap();
+        assert_eq!(frame.opcode, OPCODE_TEXT);
+        assert_eq!(frame.payload, b"Hello");
+        let frame = decode_frame(&mut input).unwrap();
+        assert_eq!(frame.opcode, OPCODE_PING);
+        assert!(frame.payload.is_empty());
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn encodes_and_decodes_extended_lengths() {
+        for (len, header) in [
+            (125, vec![0x81, 125]),
+            (126, vec![0x81, 126, 0x00, 126]),
+            (0xffff, vec![0x81, 126, 0xff, 0xff]),
+            (0x10000, vec![0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]),
+        ] {
+            let payload: Vec<u8> = (0..len).map(|idx| idx as u8).collect();
+            let mut frame = encode_frame(OPCODE_TEXT, &payload);
+            assert_eq!(frame[..header.len()], header);
+            assert_eq!(frame.len(), header.len() + len);
+            let decoded = decode_frame(&mut frame).unwrap();
+            assert_eq!(decoded.opcode, OPCODE_TEXT);
+            assert_eq!(decoded.payload, payload);
+            assert!(frame.is_empty());
+        }
+    }
+
+    #[test]
+    fn waits_for_partial_frames() {
+        let frame = encode_frame(OPCODE_TEXT, &[b'x'; 300]);
+        for len in 0..frame.len() {
+            let mut input = frame[..len].to_vec();
+            assert!(decode_frame(&mut input).is_none(), "{} bytes", len);
+            assert_eq!(input, frame[..len]);
+        }
+        // a length no buffer can hold is still only a partial frame
+        let mut input = vec![0x81, 127, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+        assert!(decode_frame(&mut input).is_none());
+    }
+}
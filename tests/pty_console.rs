@@ -0,0 +1,1298 @@
+//! Drives the whole console over pseudo-terminals: each processor is the
+//! slave side of a PTY pair whose master plays the device, and the console's
+//! own stdin/stdout is another PTY standing in for the user's terminal.
+#![cfg(unix)]
+
+use jfconsole::utils::compression::open_maybe_compressed;
+use serialport::{SerialPort, TTYPort};
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+
+/// The device end of a processor's port.
+struct Device {
+    master: TTYPort,
+    // held open so the master never reads a hang up before the console opens it
+    slave: TTYPort,
+}
+
+impl Device {
+    fn new() -> Self {
+        let (mut master, slave) = TTYPort::pair().unwrap();
+        master.set_timeout(POLL_PERIOD).unwrap();
+        Self { master, slave }
+    }
+
+    fn path(&self) -> String {
+        self.slave.name().unwrap()
+    }
+
+    fn send(&mut self, mut bytes: &[u8]) {
+        // a full PTY buffer times out rather than blocking
+        while !bytes.is_empty() {
+            match self.master.write(bytes) {
+                Ok(count) => bytes = &bytes[count..],
+                Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => panic!("device write failed: {:?}", e),
+            }
+        }
+    }
+
+    /// Reads until `needle` has been received and returns everything read.
+    fn expect(&mut self, needle: &str) -> String {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut received = vec![];
+        let mut buf = [0u8; 256];
+        while !String::from_utf8_lossy(&received).contains(needle) {
+            assert!(
+                Instant::now() < deadline,
+                "device never received {:?}, got {:?}",
+                needle,
+                String::from_utf8_lossy(&received)
+            );
+            match self.master.read(&mut buf) {
+                Ok(count) => received.extend_from_slice(&buf[..count]),
+                Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => panic!("device read failed: {:?}", e),
+            }
+        }
+        String::from_utf8_lossy(&received).into_owned()
+    }
+
+    /// Everything received within `period`.
+    fn drain_for(&mut self, period: Duration) -> String {
+        let deadline = Instant::now() + period;
+        let mut received = vec![];
+        let mut buf = [0u8; 256];
+        while Instant::now() < deadline {
+            if let Ok(count) = self.master.read(&mut buf) {
+                received.extend_from_slice(&buf[..count]);
+            }
+        }
+        String::from_utf8_lossy(&received).into_owned()
+    }
+}
+
+/// A running console with a PTY for a terminal.
+struct Console {
+    child: Child,
+    terminal: TTYPort,
+    // held open so whatever a console prints right before exiting is not
+    // lost to the hang up
+    _user_side: TTYPort,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Console {
+    fn spawn(args: &[&str]) -> Self {
+        let mut console = Self::start(args, &std::env::current_dir().unwrap());
+        console.expect("cmd history");
+        console
+    }
+
+    /// Starts the console in `dir` without waiting for it to be ready.
+    fn start(args: &[&str], dir: &Path) -> Self {
+        Self::start_with_stdin(args, dir, None)
+    }
+
+    /// Like [`Console::start`], but reading `stdin` in place of the
+    /// terminal if one is given.
+    fn start_with_stdin(args: &[&str], dir: &Path, stdin: Option<Stdio>) -> Self {
+        let (terminal, user_side) = TTYPort::pair().unwrap();
+        let user_path = user_side.name().unwrap();
+        let open_user_side = || {
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .open(&user_path)
+                .unwrap();
+            Stdio::from(file)
+        };
+        let child = Command::new(env!("CARGO_BIN_EXE_jfconsole"))
+            .args(args)
+            .current_dir(dir)
+            .env("TERM", "xterm")
+            .stdin(stdin.unwrap_or_else(open_user_side))
+            .stdout(open_user_side())
+            .stderr(open_user_side())
+            .spawn()
+            .unwrap();
+
+        // keep the terminal drained so the console never blocks on stdout
+        let output = Arc::new(Mutex::new(vec![]));
+        let mut reader = terminal.try_clone_native().unwrap();
+        reader.set_timeout(POLL_PERIOD).unwrap();
+        let reader_output = output.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(count) => reader_output
+                        .lock()
+                        .unwrap()
+                        .extend_from_slice(&buf[..count]),
+                    Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Self {
+            child,
+            terminal,
+            _user_side: user_side,
+            output,
+        }
+    }
+
+    fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.lock().unwrap()).into_owned()
+    }
+
+    fn expect(&mut self, needle: &str) {
+        let deadline = Instant::now() + TIMEOUT;
+        while !self.output().contains(needle) {
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                panic!("console never printed {:?}:\n{}", needle, self.output());
+            }
+            thread::sleep(POLL_PERIOD);
+        }
+    }
+
+    fn type_keys(&mut self, keys: &str) {
+        self.terminal.write_all(keys.as_bytes()).unwrap();
+        // let the line editor handle each burst of keys separately
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    /// Ctrl-C, then waits for a clean exit.
+    fn exit(mut self) {
+        self.type_keys("\x03");
+        self.wait_exit();
+    }
+
+    /// Waits for the console to exit on its own with a clean status.
+    fn wait_exit(mut self) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                assert!(status.success(), "console exited with {}", status);
+                return;
+            }
+            if Instant::now() >= deadline {
+                let _ = self.child.kill();
+                panic!("console did not exit:\n{}", self.output());
+            }
+            thread::sleep(POLL_PERIOD);
+        }
+    }
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jfconsole_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn log_paths(log_dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(log_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            name.ends_with(".log") || name.ends_with(".log.gz") || name.ends_with(".log.zst")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// All log segments, oldest first, decompressed.
+fn read_log(log_dir: &Path) -> String {
+    let paths = log_paths(log_dir);
+    assert!(!paths.is_empty(), "no log file written");
+    paths
+        .iter()
+        .map(|path| {
+            let mut log = String::new();
+            open_maybe_compressed(path)
+                .unwrap()
+                .read_to_string(&mut log)
+                .unwrap();
+            log
+        })
+        .collect()
+}
+
+/// A TCP port that was free a moment ago.
+fn free_tcp_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+fn connect_bridge(port: u16) -> TcpStream {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(POLL_PERIOD)).unwrap();
+    stream
+}
+
+/// Reads from `stream` until the bytes received contain `needle`.
+fn expect_bytes(stream: &mut impl Read, needle: &[u8]) -> Vec<u8> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut received = vec![];
+    let mut buf = [0u8; 256];
+    while !received.windows(needle.len()).any(|w| w == needle) {
+        assert!(
+            Instant::now() < deadline,
+            "client never received {:?}, got {:?}",
+            needle,
+            received
+        );
+        match stream.read(&mut buf) {
+            Ok(0) => panic!("peer closed the connection"),
+            Ok(count) => received.extend_from_slice(&buf[..count]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => panic!("client read failed: {:?}", e),
+        }
+    }
+    received
+}
+
+/// Sends a whole HTTP request and returns the response once the server
+/// closes the connection.
+fn http_request(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn log_has_line(log: &str, prefix: &str, text: &str) -> bool {
+    log.lines()
+        .any(|line| line.starts_with(prefix) && line.ends_with(&format!("| {}", text)))
+}
+
+/// Waits for the file logger to write a line `log_has_line` would find.
+fn wait_for_log_line(log_dir: &Path, prefix: &str, text: &str) -> String {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        if !log_paths(log_dir).is_empty() {
+            let log = read_log(log_dir);
+            if log_has_line(&log, prefix, text) {
+                return log;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "never logged {:?}:\n{}",
+                text,
+                log
+            );
+        }
+        thread::sleep(POLL_PERIOD);
+    }
+}
+
+/// The text of every line logged under `prefix`, wrapped lines rejoined.
+/// Lines are `<prefix> <date> <time> <complete> <text>`, and the date and
+/// time take 21 chars, so the text starts 24 chars after the prefix.
+fn logged_text(log: &str, prefix: &str) -> String {
+    log.lines()
+        .filter_map(|line| line.strip_prefix(prefix))
+        .filter_map(|rest| rest.get(24..))
+        .collect()
+}
+
+#[test]
+fn logs_device_output_and_typed_commands() {
+    let log_dir = test_dir("log");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&["--port", &port, "--log-dir", log_dir.to_str().unwrap()]);
+
+    device.send(b"boot ok\r\n");
+    console.expect("boot ok");
+    console.type_keys("status\r");
+    device.expect("status\r");
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev r ", "boot ok"), "{}", log);
+    assert!(log_has_line(&log, "dev w ", "status"), "{}", log);
+}
+
+#[test]
+fn saves_and_recovers_cmd_history() {
+    let log_dir = test_dir("history");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let args = ["--port", &port, "--log-dir", log_dir.to_str().unwrap()];
+
+    let mut console = Console::spawn(&args);
+    console.type_keys("first cmd\r");
+    device.expect("first cmd\r");
+    console.exit();
+    let history = fs::read_to_string(log_dir.join("dev cmd history.txt")).unwrap();
+    assert!(history.contains("first cmd"), "{}", history);
+
+    let mut console = Console::spawn(&args);
+    console.expect("recovered dev cmd history");
+    // up arrow recalls the previous session's command
+    console.type_keys("\x1b[A");
+    console.type_keys("\r");
+    device.expect("first cmd\r");
+    console.exit();
+}
+
+#[test]
+fn switches_processor_on_eof() {
+    let log_dir = test_dir("switch");
+    let (mut device_a, mut device_b) = (Device::new(), Device::new());
+    let port_a = format!("{}:115200:a", device_a.path());
+    let port_b = format!("{}:115200:b", device_b.path());
+    let log_dir_arg = log_dir.to_str().unwrap();
+    let mut console = Console::spawn(&[
+        "--port",
+        &port_a,
+        "--port",
+        &port_b,
+        "--log-dir",
+        log_dir_arg,
+    ]);
+
+    console.type_keys("to a\r");
+    device_a.expect("to a\r");
+    // Ctrl-D on an empty line moves on to the next processor
+    console.type_keys("\x04");
+    console.expect("switching to \"b\"");
+    console.type_keys("to b\r");
+    device_b.expect("to b\r");
+    assert!(!device_a
+        .drain_for(Duration::from_millis(200))
+        .contains("to b"));
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "a w ", "to a"), "{}", log);
+    assert!(log_has_line(&log, "b w ", "to b"), "{}", log);
+}
+
+#[test]
+fn keeps_running_after_piped_stdin_closes() {
+    let log_dir = test_dir("piped");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let args = [
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--control",
+    ];
+    let cwd = std::env::current_dir().unwrap();
+    let mut console = Console::start_with_stdin(&args, &cwd, Some(Stdio::null()));
+
+    console.expect("stdin closed");
+    device.send(b"still logging\r\n");
+    console.expect("still logging");
+    let mut client = UnixStream::connect(log_dir.join("jfconsole.sock")).unwrap();
+    client.set_read_timeout(Some(POLL_PERIOD)).unwrap();
+    client.write_all(b"quit\n").unwrap();
+    expect_bytes(&mut client, b"ok\n");
+    let output = console.output();
+    console.wait_exit();
+    assert!(!output.contains("switching to"), "{}", output);
+}
+
+#[test]
+fn sends_piped_stdin_and_exits_on_sigint() {
+    let log_dir = test_dir("piped_sigint");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let args = ["--port", &port, "--log-dir", log_dir.to_str().unwrap()];
+    let cwd = std::env::current_dir().unwrap();
+    let mut console = Console::start_with_stdin(&args, &cwd, Some(Stdio::piped()));
+
+    let mut stdin = console.child.stdin.take().unwrap();
+    stdin.write_all(b"first\nsecond\n").unwrap();
+    device.expect("second\r");
+    drop(stdin);
+    console.expect("stdin closed");
+    let status = Command::new("kill")
+        .args(["-INT", &console.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    console.wait_exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev w ", "first"), "{}", log);
+    assert!(log_has_line(&log, "dev w ", "second"), "{}", log);
+}
+
+#[test]
+fn loopback_echoes_typed_lines() {
+    let log_dir = test_dir("loopback");
+    let mut console = Console::spawn(&[
+        "--port",
+        "loop://:115200:lo",
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ]);
+
+    console.type_keys("ping\r");
+    console.expect("lo r");
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "lo w ", "ping"), "{}", log);
+    assert!(log_has_line(&log, "lo r ", "ping"), "{}", log);
+}
+
+#[test]
+fn pty_port_talks_to_a_simulator_on_its_slave() {
+    let log_dir = test_dir("pty_port");
+    let mut console = Console::spawn(&[
+        "--port",
+        "pty://:115200:sim",
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ]);
+
+    console.expect("is on pty ");
+    let output = console.output();
+    let (_, rest) = output.split_once("is on pty ").unwrap();
+    let slave_path = rest.split_whitespace().next().unwrap();
+    let mut simulator = serialport::new(slave_path, 115200)
+        .timeout(POLL_PERIOD)
+        .open_native()
+        .unwrap();
+
+    simulator.write_all(b"sim ready\r\n").unwrap();
+    console.expect("sim ready");
+    console.type_keys("status\r");
+    let deadline = Instant::now() + TIMEOUT;
+    let mut received = vec![];
+    let mut buf = [0u8; 256];
+    while !String::from_utf8_lossy(&received).contains("status\r") {
+        assert!(Instant::now() < deadline, "simulator got {:?}", received);
+        match simulator.read(&mut buf) {
+            Ok(count) => received.extend_from_slice(&buf[..count]),
+            Err(e) if e.kind() == ErrorKind::TimedOut => {}
+            Err(e) => panic!("simulator read failed: {:?}", e),
+        }
+    }
+    console.exit();
+}
+
+#[test]
+fn writes_long_lines_to_loopback_once() {
+    let dir = test_dir("long_line");
+    let config = r#"{
+        "project_name": "long_line",
+        "processors": [{
+            "processor_name": "lo",
+            "baud_rate": 115200,
+            "port_name": "loop://",
+            "tx_char_delay_us": 0
+        }]
+    }"#;
+    let config_path = dir.join("long_line.json");
+    fs::write(&config_path, config).unwrap();
+    let log_dir = dir.join("logs");
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ];
+    let mut console = Console::start_with_stdin(&args, &dir, Some(Stdio::piped()));
+    console.expect("cmd history");
+
+    // far more than a socket buffer, so the port takes it in partial writes
+    let line = "012345678 ".repeat(30_000);
+    let mut stdin = console.child.stdin.take().unwrap();
+    stdin
+        .write_all(format!("{}\ndone\n", line).as_bytes())
+        .unwrap();
+    let log = wait_for_log_line(&log_dir, "lo r ", "done");
+    drop(stdin);
+    console.expect("stdin closed");
+    let status = Command::new("kill")
+        .args(["-INT", &console.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    console.wait_exit();
+
+    let expected = format!("{}done", line);
+    assert_eq!(logged_text(&log, "lo w "), expected);
+    assert_eq!(logged_text(&log, "lo r "), expected);
+}
+
+#[test]
+fn replays_a_capture_of_a_session() {
+    let log_dir = test_dir("capture");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--capture",
+    ]);
+
+    device.send(b"boot ok\r\n");
+    console.expect("boot ok");
+    console.type_keys("status\r");
+    device.expect("status\r");
+    console.exit();
+
+    let cap_path = fs::read_dir(&log_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.to_string_lossy().ends_with(".cap"))
+        .expect("no capture written");
+    let args = [
+        "--replay",
+        cap_path.to_str().unwrap(),
+        "--replay-speed",
+        "0",
+    ];
+    let mut replay = Console::start(&args, &log_dir);
+    replay.expect("status");
+    let output = replay.output();
+    replay.wait_exit();
+    let rx = output.lines().find(|line| line.contains("boot ok"));
+    assert!(rx.is_some_and(|line| line.contains("dev r")), "{}", output);
+    let tx = output.lines().find(|line| line.contains("status"));
+    assert!(tx.is_some_and(|line| line.contains("dev w")), "{}", output);
+}
+
+/// The text of every line the console printed under `prefix`, wrapped lines
+/// rejoined. Console lines carry a 9 char `MM:SS.mmm` time in place of the
+/// log's date and time.
+fn printed_text(output: &str, prefix: &str) -> String {
+    output
+        .lines()
+        .filter_map(|line| line.trim_end_matches('\r').strip_prefix(prefix))
+        .filter_map(|rest| rest.get(12..))
+        .collect()
+}
+
+#[test]
+fn replays_wrapped_lines_and_only_reports_triggers() {
+    let dir = test_dir("replay_wrapped");
+    let mut device = Device::new();
+    let config = format!(
+        r#"{{
+            "project_name": "replay_wrapped",
+            "processors": [{{
+                "processor_name": "dev",
+                "baud_rate": 115200,
+                "port_name": "{}",
+                "triggers": [{{ "pattern": "panic at (\\w+)", "response": "reset $1" }}]
+            }}]
+        }}"#,
+        device.path()
+    );
+    let config_path = dir.join("replay_wrapped.json");
+    fs::write(&config_path, config).unwrap();
+    let log_dir = dir.join("logs");
+    let mut console = Console::spawn(&[
+        "--config",
+        config_path.to_str().unwrap(),
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--capture",
+    ]);
+
+    // wider than a console line, so it is split over several
+    let line = format!("{}panic at boot", "word ".repeat(300));
+    device.send(format!("{}\r\n", line).as_bytes());
+    device.expect("reset boot\r");
+    console.expect("triggered");
+    console.exit();
+
+    let recorded: Vec<_> = fs::read_dir(&log_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.to_string_lossy();
+            name.ends_with(".cap") || name.ends_with(".log")
+        })
+        .collect();
+    assert_eq!(recorded.len(), 2, "{:?}", recorded);
+    for path in recorded {
+        let args = [
+            "--replay",
+            path.to_str().unwrap(),
+            "--replay-speed",
+            "0",
+            "--config",
+            config_path.to_str().unwrap(),
+        ];
+        let mut replay = Console::start(&args, &dir);
+        replay.expect("replayed");
+        let output = replay.output();
+        replay.wait_exit();
+
+        assert!(
+            output.contains("triggered \"reset boot\" to dev"),
+            "{}",
+            output
+        );
+        let rx_lines = output.lines().filter(|l| l.starts_with("dev r ")).count();
+        assert!(rx_lines > 1, "{:?} was not wrapped:\n{}", path, output);
+        assert_eq!(printed_text(&output, "dev r "), line, "{:?}", path);
+        assert!(!device
+            .drain_for(Duration::from_millis(200))
+            .contains("reset"));
+    }
+}
+
+#[test]
+fn rejects_capturing_more_processors_than_it_can_index() {
+    let dir = test_dir("capture_limit");
+    let processors: Vec<_> = (0..257)
+        .map(|idx| {
+            format!(
+                r#"{{ "processor_name": "p{}", "baud_rate": 115200, "port_name": "loop://" }}"#,
+                idx
+            )
+        })
+        .collect();
+    let config = format!(
+        r#"{{ "project_name": "capture_limit", "processors": [{}] }}"#,
+        processors.join(", ")
+    );
+    let config_path = dir.join("capture_limit.json");
+    fs::write(&config_path, config).unwrap();
+    let args = ["--config", config_path.to_str().unwrap(), "--capture"];
+    let mut console = Console::start(&args, &dir);
+
+    console.expect("--capture records at most 256 processors");
+    console.wait_exit();
+}
+
+#[test]
+fn trigger_auto_responds() {
+    let dir = test_dir("trigger");
+    let mut device = Device::new();
+    let config = format!(
+        r#"{{
+            "project_name": "trigger",
+            "processors": [{{
+                "processor_name": "dev",
+                "baud_rate": 115200,
+                "port_name": "{}",
+                "triggers": [{{ "pattern": "login: (\\w+)?$", "response": "root" }}]
+            }}]
+        }}"#,
+        device.path()
+    );
+    let config_path = dir.join("trigger.json");
+    fs::write(&config_path, config).unwrap();
+    let log_dir = dir.join("logs");
+    let mut console = Console::spawn(&[
+        "--config",
+        config_path.to_str().unwrap(),
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ]);
+
+    device.send(b"board login: \r\n");
+    device.expect("root\r");
+    console.expect("triggered");
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev r ", "board login: "), "{}", log);
+}
+
+#[test]
+fn trigger_responds_on_other_processor() {
+    let dir = test_dir("trigger_target");
+    let (mut device_a, mut device_b) = (Device::new(), Device::new());
+    let config = format!(
+        r#"{{
+            "project_name": "trigger_target",
+            "processors": [{{
+                "processor_name": "a",
+                "baud_rate": 115200,
+                "port_name": "{}",
+                "triggers": [{{ "pattern": "a ready", "response": "start", "target": "b" }}]
+            }}, {{
+                "processor_name": "b",
+                "baud_rate": 115200,
+                "port_name": "{}"
+            }}]
+        }}"#,
+        device_a.path(),
+        device_b.path()
+    );
+    let config_path = dir.join("trigger_target.json");
+    fs::write(&config_path, config).unwrap();
+    let log_dir = dir.join("logs");
+    let mut console = Console::spawn(&[
+        "--config",
+        config_path.to_str().unwrap(),
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ]);
+
+    device_a.send(b"a ready\r\n");
+    device_b.expect("start\r");
+    console.expect("triggered");
+    assert!(!device_a
+        .drain_for(Duration::from_millis(200))
+        .contains("start"));
+    console.exit();
+}
+
+#[test]
+fn rejects_duplicate_processor_names() {
+    let dir = test_dir("duplicate_name");
+    let (device_a, device_b) = (Device::new(), Device::new());
+    let config = format!(
+        r#"{{
+            "project_name": "duplicate_name",
+            "processors": [
+                {{ "processor_name": "dev", "baud_rate": 115200, "port_name": "{}" }},
+                {{ "processor_name": "dev", "baud_rate": 115200, "port_name": "{}" }}
+            ]
+        }}"#,
+        device_a.path(),
+        device_b.path()
+    );
+    let config_path = dir.join("duplicate_name.json");
+    fs::write(&config_path, config).unwrap();
+    let mut console = Console::start(&["--config", config_path.to_str().unwrap()], &dir);
+
+    console.expect("duplicate processor_name \"dev\"");
+    console.wait_exit();
+}
+
+#[test]
+fn rotates_log_by_size_and_keeps_newest() {
+    let log_dir = test_dir("rotate");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-max-size",
+        "1",
+        "--log-keep",
+        "2",
+    ]);
+
+    // about 2.7MB of log lines, so three 1MB segments
+    let line = format!("{}\r\n", "x".repeat(100));
+    for _ in 0..20_000 {
+        device.send(line.as_bytes());
+    }
+    device.send(b"done\r\n");
+    console.expect("| done");
+    console.exit();
+
+    let paths = log_paths(&log_dir);
+    let names: Vec<_> = paths
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+    assert!(names[0].ends_with("_001.log"), "{:?}", names);
+    assert!(names[1].ends_with("_002.log"), "{:?}", names);
+    for path in paths {
+        assert!(fs::metadata(path).unwrap().len() <= 1 << 20);
+    }
+}
+
+#[test]
+fn rotates_log_by_interval_while_idle() {
+    let log_dir = test_dir("rotate_interval");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    // two seconds
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-interval",
+        "0.034",
+    ]);
+
+    device.send(b"before\r\n");
+    console.expect("| before");
+    // nothing else is logged, so only the idle wake up can rotate
+    let deadline = Instant::now() + TIMEOUT;
+    while log_paths(&log_dir).len() < 2 {
+        assert!(Instant::now() < deadline, "never rotated while idle");
+        thread::sleep(POLL_PERIOD);
+    }
+    console.exit();
+
+    let paths = log_paths(&log_dir);
+    assert_eq!(paths.len(), 2, "{:?}", paths);
+    assert!(paths[0].to_string_lossy().ends_with("_000.log"));
+    let first = fs::read_to_string(&paths[0]).unwrap();
+    assert!(log_has_line(&first, "dev r ", "before"), "{}", first);
+}
+
+#[test]
+fn writes_gzip_log() {
+    let log_dir = test_dir("gzip");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-compress",
+        "gzip",
+    ]);
+
+    device.send(b"boot ok\r\n");
+    console.expect("boot ok");
+    console.exit();
+
+    let paths = log_paths(&log_dir);
+    assert_eq!(paths.len(), 1, "{:?}", paths);
+    assert!(paths[0].to_string_lossy().ends_with(".log.gz"));
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev r ", "boot ok"), "{}", log);
+}
+
+#[test]
+fn writes_zstd_log() {
+    let log_dir = test_dir("zstd");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-compress",
+        "zstd",
+    ]);
+
+    device.send(b"boot ok\r\n");
+    console.expect("boot ok");
+    console.exit();
+
+    let paths = log_paths(&log_dir);
+    assert_eq!(paths.len(), 1, "{:?}", paths);
+    assert!(paths[0].to_string_lossy().ends_with(".log.zst"));
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev r ", "boot ok"), "{}", log);
+}
+
+#[test]
+fn replays_what_precedes_a_cut_in_a_gzip_log() {
+    let log_dir = test_dir("gzip_cut");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-compress",
+        "gzip",
+    ]);
+
+    for idx in 0..2000 {
+        device.send(format!("line {} of the session\r\n", idx).as_bytes());
+    }
+    console.expect("line 1999");
+    console.exit();
+
+    // as if the session died halfway through writing the file
+    let path = &log_paths(&log_dir)[0];
+    let gz = fs::read(path).unwrap();
+    fs::write(path, &gz[..gz.len() / 2]).unwrap();
+
+    let args = ["--replay", path.to_str().unwrap(), "--replay-speed", "0"];
+    let mut replay = Console::start(&args, &log_dir);
+    replay.expect("replayed");
+    let output = replay.output();
+    replay.wait_exit();
+    assert!(output.contains("is cut short"), "{}", output);
+    assert!(output.contains("| line 0 of the session"), "{}", output);
+    assert!(!output.contains("line 1999"), "{}", output);
+}
+
+#[test]
+fn compresses_rotated_segments() {
+    let log_dir = test_dir("compress");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--log-max-size",
+        "1",
+        "--log-compress",
+        "rotated",
+    ]);
+
+    let line = format!("{}\r\n", "x".repeat(100));
+    for _ in 0..20_000 {
+        device.send(line.as_bytes());
+    }
+    device.send(b"done\r\n");
+    console.expect("| done");
+    console.exit();
+
+    let names: Vec<_> = log_paths(&log_dir)
+        .iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names.len(), 3, "{:?}", names);
+    assert!(names[0].ends_with("_000.log.gz"), "{:?}", names);
+    assert!(names[1].ends_with("_001.log.gz"), "{:?}", names);
+    assert!(names[2].ends_with("_002.log"), "{:?}", names);
+    let log = read_log(&log_dir);
+    assert_eq!(
+        log.lines().filter(|line| line.ends_with("xxx")).count(),
+        20_000
+    );
+    assert!(
+        log_has_line(&log, "dev r ", "done"),
+        "{}",
+        &log[log.len() - 200..]
+    );
+}
+
+#[test]
+fn bridges_port_over_raw_tcp() {
+    let log_dir = test_dir("bridge");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let tcp_port = free_tcp_port().to_string();
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--bridge",
+        &tcp_port,
+    ]);
+
+    // only an explicit --bridge-bind serves the network
+    console.expect(&format!("serving dev on 127.0.0.1:{}", tcp_port));
+    let mut client = connect_bridge(tcp_port.parse().unwrap());
+    console.expect("connected");
+    device.send(b"boot ok\r\n");
+    expect_bytes(&mut client, b"boot ok\r\n");
+    client.write_all(b"remote cmd\r").unwrap();
+    device.expect("remote cmd\r");
+    // the local console still works alongside
+    console.type_keys("local cmd\r");
+    device.expect("local cmd\r");
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev w ", "remote cmd"), "{}", log);
+    assert!(log_has_line(&log, "dev w ", "local cmd"), "{}", log);
+}
+
+#[test]
+fn bridges_port_over_telnet() {
+    let log_dir = test_dir("telnet");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let tcp_port = free_tcp_port().to_string();
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--bridge",
+        &tcp_port,
+        "--bridge-protocol",
+        "telnet",
+        "--bridge-bind",
+        "127.0.0.1",
+    ]);
+
+    let mut client = connect_bridge(tcp_port.parse().unwrap());
+    console.expect("connected");
+    // IAC WILL ECHO
+    expect_bytes(&mut client, &[255, 251, 1]);
+    // IAC DO TERMINAL-TYPE is refused with IAC WONT TERMINAL-TYPE
+    client.write_all(&[255, 253, 24]).unwrap();
+    expect_bytes(&mut client, &[255, 252, 24]);
+
+    // 255 goes out doubled, and comes in undoubled with CR NUL as CR
+    device.send(b"a\xffb\r\n");
+    expect_bytes(&mut client, b"a\xff\xffb\r\n");
+    client.write_all(b"c\xff\xffd\r\0").unwrap();
+    let mut received = vec![];
+    let deadline = Instant::now() + TIMEOUT;
+    while !received.ends_with(b"c\xffd\r") {
+        assert!(Instant::now() < deadline, "device got {:?}", received);
+        let mut buf = [0u8; 64];
+        if let Ok(count) = device.master.read(&mut buf) {
+            received.extend_from_slice(&buf[..count]);
+        }
+    }
+    console.exit();
+}
+
+#[test]
+fn drives_rfc2217_server_port() {
+    let log_dir = test_dir("rfc2217");
+    // stands in for ser2net
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = format!(
+        "rfc2217://127.0.0.1:{}:9600:remote",
+        server.local_addr().unwrap().port()
+    );
+    let mut console = Console::spawn(&["--port", &port, "--log-dir", log_dir.to_str().unwrap()]);
+    let (mut conn, _) = server.accept().unwrap();
+    conn.set_read_timeout(Some(POLL_PERIOD)).unwrap();
+
+    // SET-CONTROL no flow control comes last
+    let setup = expect_bytes(&mut conn, &[255, 250, 44, 5, 1, 255, 240]);
+    let sent = |needle: &[u8]| setup.windows(needle.len()).any(|w| w == needle);
+    // IAC WILL COM-PORT-OPTION
+    assert!(sent(&[255, 251, 44]), "{:?}", setup);
+    // IAC SB COM-PORT-OPTION SET-BAUDRATE 9600 IAC SE
+    assert!(
+        sent(&[255, 250, 44, 1, 0, 0, 0x25, 0x80, 255, 240]),
+        "{:?}",
+        setup
+    );
+    // SET-PARITY none
+    assert!(sent(&[255, 250, 44, 3, 1, 255, 240]), "{:?}", setup);
+    conn.write_all(&[255, 253, 44, 255, 250, 44, 101, 0, 0, 0x25, 0x80, 255, 240])
+        .unwrap();
+
+    conn.write_all(b"remote \xff\xffboot\r\n").unwrap();
+    console.expect("remote \u{fffd}boot");
+    console.type_keys("status\r");
+    expect_bytes(&mut conn, b"status\r");
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "remote w ", "status"), "{}", log);
+}
+
+#[test]
+fn sends_rfc2217_writes_the_server_takes_late_once() {
+    let dir = test_dir("rfc2217_stall");
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = format!(
+        r#"{{
+            "project_name": "rfc2217_stall",
+            "processors": [{{
+                "processor_name": "remote",
+                "baud_rate": 115200,
+                "port_name": "rfc2217://127.0.0.1:{}",
+                "tx_char_delay_us": 0
+            }}]
+        }}"#,
+        server.local_addr().unwrap().port()
+    );
+    let config_path = dir.join("rfc2217_stall.json");
+    fs::write(&config_path, config).unwrap();
+    let log_dir = dir.join("logs");
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+    ];
+    let mut console = Console::start_with_stdin(&args, &dir, Some(Stdio::piped()));
+    let (mut conn, _) = server.accept().unwrap();
+    conn.set_read_timeout(Some(POLL_PERIOD)).unwrap();
+    expect_bytes(&mut conn, &[255, 250, 44, 5, 1, 255, 240]);
+    console.expect("cmd history");
+
+    // more than the socket buffers hold while the server is not reading
+    let line = "012345678 ".repeat(30_000);
+    let mut stdin = console.child.stdin.take().unwrap();
+    stdin
+        .write_all(format!("{}\ndone\n", line).as_bytes())
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    let received = expect_bytes(&mut conn, b"done\r");
+    drop(stdin);
+    console.expect("stdin closed");
+    let status = Command::new("kill")
+        .args(["-INT", &console.child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    console.wait_exit();
+
+    assert_eq!(received.len(), line.len() + "\rdone\r".len());
+    assert_eq!(received, format!("{}\rdone\r", line).as_bytes());
+}
+
+#[test]
+fn creates_config_for_entered_device_path() {
+    let dir = test_dir("custom");
+    fs::create_dir(dir.join("config")).unwrap();
+    let mut device = Device::new();
+    let mut console = Console::start(&[], &dir);
+
+    console.expect("a device path");
+    console.type_keys(&format!("{}\r", device.path()));
+    console.expect("nickname");
+    console.type_keys("dev\r");
+    // 115200 baud, 8N1, no flow control, then no more ports
+    for key in ["1\r", "1\r", "1\r", "\r"] {
+        console.type_keys(key);
+    }
+    console.expect("project name");
+    console.type_keys("custom\r");
+    console.expect("cmd history");
+    console.type_keys("hello\r");
+    device.expect("hello\r");
+    console.exit();
+
+    let config = fs::read_to_string(dir.join("config/custom.json")).unwrap();
+    assert!(config.contains(&device.path()), "{}", config);
+    assert!(config.contains(r#""port_type": "unknown""#), "{}", config);
+}
+
+#[test]
+fn scripts_session_over_control_socket() {
+    let log_dir = test_dir("control");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--control",
+    ]);
+
+    console.expect("listening on");
+    let socket_path = log_dir.join("jfconsole.sock");
+    let mut client = UnixStream::connect(&socket_path).unwrap();
+    client.set_read_timeout(Some(POLL_PERIOD)).unwrap();
+    client.write_all(b"list\n").unwrap();
+    let listed = format!("processor {} dev\nok\n", device.path());
+    expect_bytes(&mut client, listed.as_bytes());
+
+    client.write_all(b"subscribe dev\n").unwrap();
+    expect_bytes(&mut client, b"ok\n");
+    device.send(b"boot ok\r\n");
+    let received = expect_bytes(&mut client, b"| boot ok\n");
+    assert!(received.starts_with(b"line dev r "), "{:?}", received);
+
+    client.write_all(b"send dev status\n").unwrap();
+    device.expect("status\r");
+    expect_bytes(&mut client, b"ok\n");
+    client.write_all(b"send nope status\n").unwrap();
+    expect_bytes(&mut client, b"error no processor");
+    client.write_all(b"marker step 2\n").unwrap();
+    expect_bytes(&mut client, b"# step 2\n");
+    console.exit();
+    assert!(!socket_path.exists());
+
+    let log = read_log(&log_dir);
+    assert!(log_has_line(&log, "dev w ", "status"), "{}", log);
+    assert!(log
+        .lines()
+        .any(|line| line.starts_with("dev w ") && line.ends_with("# step 2")));
+}
+
+#[test]
+fn serves_dashboard_and_websocket() {
+    let log_dir = test_dir("http");
+    let mut device = Device::new();
+    let port = format!("{}:115200:dev", device.path());
+    let tcp_port = free_tcp_port();
+    let mut console = Console::spawn(&[
+        "--port",
+        &port,
+        "--log-dir",
+        log_dir.to_str().unwrap(),
+        "--http",
+        &tcp_port.to_string(),
+    ]);
+    console.expect("dashboard on");
+
+    let page = http_request(tcp_port, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert!(page.starts_with("HTTP/1.1 200 OK"), "{}", page);
+    assert!(page.contains("<title>jfconsole</title>"));
+    let listed = http_request(
+        tcp_port,
+        "GET /processors HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let processors = format!(r#"[{{"name":"dev","port":"{}"}}]"#, device.path());
+    assert!(listed.ends_with(&processors), "{}", listed);
+
+    // the sample handshake from RFC 6455
+    let mut ws = connect_bridge(tcp_port);
+    ws.write_all(
+        b"GET /ws HTTP/1.1\r\nHost: 127.0.0.1\r\nUpgrade: websocket\r\n\
+        Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n\r\n",
+    )
+    .unwrap();
+    expect_bytes(
+        &mut ws,
+        b"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+    );
+    device.send(b"boot ok\r\n");
+    let frames = expect_bytes(&mut ws, br#""text":"boot ok"}"#);
+    assert_eq!(frames[0], 0x81, "{:?}", frames);
+    assert!(frames
+        .windows(28)
+        .any(|w| w == br#"{"processor":"dev","dir":"r""#));
+
+    let body = r#"{"processor":"dev","line":"status"}"#;
+    let send = |origin: &str| {
+        http_request(
+            tcp_port,
+            &format!(
+                "POST /send HTTP/1.1\r\nHost: localhost:{}\r\nOrigin: {}\r\n\
+                Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                tcp_port,
+                origin,
+                body.len(),
+                body
+            ),
+        )
+    };
+    let refused = send("http://example.com");
+    assert!(refused.starts_with("HTTP/1.1 403"), "{}", refused);
+    let sent = send(&format!("http://localhost:{}", tcp_port));
+    assert!(sent.starts_with("HTTP/1.1 200 OK"), "{}", sent);
+    device.expect("status\r");
+    expect_bytes(&mut ws, br#""dir":"w""#);
+    console.exit();
+
+    let log = read_log(&log_dir);
+    assert_eq!(log.matches("| status").count(), 1, "{}", log);
+}